@@ -1,45 +1,383 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use crate::std_versions::VersionConstructor;
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{punctuated::Punctuated, Token};
+
+use crate::std_versions::{
+    parse_version, Deprecation, Stability, VersionConstructor, VersionedItem, CRATES,
+};
+
+/// How to count std items that have no stability attribute.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default)]
+pub enum UnknownStability {
+    /// Leave them out of the version signature.
+    #[default]
+    Ignore,
+    /// Count them as Rust 1.0 APIs.
+    Baseline,
+    /// Use the stability of the closest annotated parent.
+    Inherit,
+}
+
+#[derive(Debug)]
+pub struct DeprecatedApi {
+    pub uses: usize,
+    pub replacement: Option<String>,
+}
 
 pub struct VersionAnalyzer<'a> {
     version_constructor: &'a VersionConstructor,
+    unknown_stability: UnknownStability,
 
     path: Vec<String>,
     nested_unsafe: usize,
+    nested_const: usize,
+    property_test_refs: usize,
+    // `extern crate std as foo` and the like, by local name.
+    extern_crates: HashMap<String, String>,
+    // Imported paths by module and local name, e.g. `[a, StdError]` for `use std::error::Error
+    // as StdError` in module `a`.
+    imports: HashMap<Vec<String>, Vec<String>>,
+    // Macros that shadow std ones when invoked by their bare name: those the crate defines and
+    // those it imports with `#[macro_use] extern crate`.
+    local_macros: HashSet<String>,
 
     pub version_counts: HashMap<String, usize>,
+    pub resolved_paths: usize,
+    pub unknown_resolutions: usize,
+    pub std_macro_uses: usize,
+    pub no_std: bool,
+    // Uses of std items by the crate the path goes through, after undoing renames. Prelude
+    // items go through `core` in `#![no_std]` crates.
+    pub std_path_uses: usize,
+    pub core_path_uses: usize,
+    pub alloc_path_uses: usize,
+    // Uses of std items by their canonical path, so that re-exports count as the same item.
+    pub std_apis: BTreeMap<String, usize>,
+    // The newest version each of them is used as, by canonical path.
+    pub std_api_versions: BTreeMap<String, String>,
+    pub deprecated_apis: HashMap<String, DeprecatedApi>,
+    pub unstable_api_uses: BTreeMap<String, usize>,
     pub total_exprs: usize,
     pub unsafe_exprs: usize,
 
     pub total_fns: usize,
     pub async_fns: usize,
+
+    pub const_fns: usize,
+    pub const_generic_params: usize,
+    pub inline_const_blocks: usize,
+    pub assoc_consts: usize,
+    pub const_api_uses: usize,
+
+    pub try_exprs: usize,
+    pub legacy_try_exprs: usize,
+    pub unwrap_calls: usize,
+    pub expect_calls: usize,
+    pub box_dyn_error_returns: usize,
+    pub error_impls: usize,
+    pub error_impls_with_source: usize,
+    pub error_impls_with_description: usize,
+    pub error_impls_with_cause: usize,
+    pub thiserror_refs: usize,
+    pub anyhow_refs: usize,
+    pub failure_refs: usize,
+
+    pub test_fns: usize,
+    pub doc_tests: usize,
+    pub property_tests: usize,
 }
 
 impl<'a> VersionAnalyzer<'a> {
-    pub fn new(version_constructor: &'a VersionConstructor) -> VersionAnalyzer<'a> {
+    pub fn new(
+        version_constructor: &'a VersionConstructor,
+        unknown_stability: UnknownStability,
+    ) -> VersionAnalyzer<'a> {
         VersionAnalyzer {
             version_constructor,
+            unknown_stability,
 
             path: Vec::new(),
             nested_unsafe: 0,
+            nested_const: 0,
+            property_test_refs: 0,
+            extern_crates: HashMap::new(),
+            imports: HashMap::new(),
+            local_macros: HashSet::new(),
 
             version_counts: HashMap::new(),
+            resolved_paths: 0,
+            unknown_resolutions: 0,
+            std_macro_uses: 0,
+            no_std: false,
+            std_path_uses: 0,
+            core_path_uses: 0,
+            alloc_path_uses: 0,
+            std_apis: BTreeMap::new(),
+            std_api_versions: BTreeMap::new(),
+            deprecated_apis: HashMap::new(),
+            unstable_api_uses: BTreeMap::new(),
             total_exprs: 0,
             unsafe_exprs: 0,
 
             total_fns: 0,
             async_fns: 0,
+
+            const_fns: 0,
+            const_generic_params: 0,
+            inline_const_blocks: 0,
+            assoc_consts: 0,
+            const_api_uses: 0,
+
+            try_exprs: 0,
+            legacy_try_exprs: 0,
+            unwrap_calls: 0,
+            expect_calls: 0,
+            box_dyn_error_returns: 0,
+            error_impls: 0,
+            error_impls_with_source: 0,
+            error_impls_with_description: 0,
+            error_impls_with_cause: 0,
+            thiserror_refs: 0,
+            anyhow_refs: 0,
+            failure_refs: 0,
+
+            test_fns: 0,
+            doc_tests: 0,
+            property_tests: 0,
         }
     }
 
     pub fn process_file(&mut self, file: syn::File) {
+        self.count_doc_tests(&file.attrs);
+        self.no_std |= file.attrs.iter().any(|attr| attr.path().is_ident("no_std"));
+        self.record_extern_crates(&file.items);
+        self.record_imports(&mut Vec::new(), &file.items);
         for item in file.items {
             self.process_item(item);
         }
     }
 
+    /// Counts std macro invocations in unexpanded source, as expansion erases them.
+    pub fn process_macro_invocations(&mut self, module: &[String], source: &str) {
+        let Ok(tokens) = source.parse::<TokenStream>() else {
+            return;
+        };
+
+        let mut invocations = Vec::new();
+        find_macro_invocations(tokens, &mut invocations);
+
+        for path in invocations {
+            if let [name] = path.as_slice() {
+                if self.local_macros.contains(name) {
+                    continue;
+                }
+            }
+
+            self.path = module.to_vec();
+            let path = self.unrename_crate(&path);
+            self.path.clear();
+
+            // Bare names resolve to the crate that defines the macro.
+            if let Some((full_path, item)) = self.version_constructor.get_macro(&path) {
+                self.std_macro_uses += 1;
+                self.count_crate(&full_path);
+                self.count_item(&full_path, item);
+            }
+        }
+    }
+
+    /// Records macros that are not std ones, even if they have the same name.
+    pub fn record_local_macros(&mut self, names: impl IntoIterator<Item = String>) {
+        self.local_macros.extend(names);
+    }
+
+    // Crates renamed at the crate root can be used anywhere in the crate.
+    fn record_extern_crates(&mut self, items: &[syn::Item]) {
+        for item in items {
+            let syn::Item::ExternCrate(item) = item else {
+                continue;
+            };
+
+            let Some((_, rename)) = &item.rename else {
+                continue;
+            };
+
+            if CRATES.iter().any(|crate_| item.ident == crate_) {
+                self.extern_crates
+                    .insert(rename.to_string(), item.ident.to_string());
+            }
+        }
+    }
+
+    // Paths can be used before the `use` that imports them, so we collect all imports first.
+    fn record_imports(&mut self, module: &mut Vec<String>, items: &[syn::Item]) {
+        for item in items {
+            match item {
+                syn::Item::Use(item) => self.record_use_tree(module, Vec::new(), &item.tree),
+                syn::Item::Mod(item) => {
+                    let Some((_, items)) = &item.content else {
+                        continue;
+                    };
+
+                    module.push(item.ident.to_string());
+                    self.record_imports(module, items);
+                    module.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn record_use_tree(&mut self, module: &[String], mut path: Vec<String>, tree: &syn::UseTree) {
+        let local = match tree {
+            syn::UseTree::Path(tree) => {
+                path.push(tree.ident.to_string());
+                self.record_use_tree(module, path, &tree.tree);
+                return;
+            }
+            syn::UseTree::Name(name) if name.ident == "self" => path.last().cloned(),
+            syn::UseTree::Name(name) => {
+                path.push(name.ident.to_string());
+                Some(name.ident.to_string())
+            }
+            syn::UseTree::Rename(rename) => {
+                if rename.ident != "self" {
+                    path.push(rename.ident.to_string());
+                }
+
+                Some(rename.rename.to_string())
+            }
+            syn::UseTree::Glob(_) => None,
+            syn::UseTree::Group(group) => {
+                for tree in group.items.iter() {
+                    self.record_use_tree(module, path.clone(), tree);
+                }
+
+                return;
+            }
+        };
+
+        if let Some(local) = local.filter(|local| local != "_") {
+            let mut key = module.to_vec();
+            key.push(local);
+            self.imports.insert(key, path);
+        }
+    }
+
+    // The full path of a path written in the current module, following its imports.
+    fn resolve_imports(&self, path: &[String]) -> Vec<String> {
+        let mut resolved = path.to_vec();
+        if let Some(first) = path.first() {
+            let mut key = self.path.clone();
+            key.push(first.clone());
+
+            if let Some(import) = self.imports.get(&key) {
+                resolved.splice(..1, import.iter().cloned());
+            }
+        }
+
+        self.unrename_crate(&resolved)
+    }
+
+    // Matches `std::error::Error` however it is imported, and the re-export `thiserror` derives
+    // against. A crate's own `Error` trait or `serde::de::Error` don't count.
+    fn is_error_trait(&self, path: &syn::Path) -> bool {
+        if path_ends_with(path, &["thiserror", "__private", "Error"]) {
+            return true;
+        }
+
+        let path = path
+            .segments
+            .iter()
+            .map(|segment| segment.ident.to_string())
+            .collect::<Vec<_>>();
+
+        matches!(
+            self.version_constructor
+                .canonical_path(&self.resolve_imports(&path)),
+            Some("std::error::Error" | "core::error::Error")
+        )
+    }
+
+    // Also looks inside generic arguments, to catch `Result<T, Box<dyn Error>>`.
+    fn contains_box_dyn_error(&self, ty: &syn::Type) -> bool {
+        match ty {
+            syn::Type::Group(group) => self.contains_box_dyn_error(&group.elem),
+            syn::Type::Paren(paren) => self.contains_box_dyn_error(&paren.elem),
+            syn::Type::Path(path) => path.path.segments.iter().any(|segment| {
+                let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+                    return false;
+                };
+
+                args.args.iter().any(|arg| match arg {
+                    syn::GenericArgument::Type(syn::Type::TraitObject(object)) => {
+                        segment.ident == "Box"
+                            && object.bounds.iter().any(|bound| match bound {
+                                syn::TypeParamBound::Trait(bound) => {
+                                    self.is_error_trait(&bound.path)
+                                }
+                                _ => false,
+                            })
+                    }
+                    syn::GenericArgument::Type(ty) => self.contains_box_dyn_error(ty),
+                    _ => false,
+                })
+            }),
+            _ => false,
+        }
+    }
+
+    // Undoes `extern crate std as s;` at the crate root and `use core as c;` in the current module.
+    fn unrename_crate(&self, path: &[String]) -> Vec<String> {
+        let mut path = path.to_vec();
+        let Some(first) = path.first() else {
+            return path;
+        };
+
+        let mut key = self.path.clone();
+        key.push(first.clone());
+
+        let krate = match self.imports.get(&key).map(Vec::as_slice) {
+            Some([krate]) if CRATES.contains(&krate.as_str()) => Some(krate.clone()),
+            _ => self.extern_crates.get(first).cloned(),
+        };
+
+        if let Some(krate) = krate {
+            path[0] = krate;
+        }
+
+        path
+    }
+
+    fn count_crate(&mut self, path: &[String]) {
+        match path.first().map(String::as_str) {
+            Some("core") => self.core_path_uses += 1,
+            Some("alloc") => self.alloc_path_uses += 1,
+            Some("std") => self.std_path_uses += 1,
+            _ if self.no_std => self.core_path_uses += 1,
+            _ => self.std_path_uses += 1,
+        }
+    }
+
+    /// Processes only the parts of a file expanded with `--tests` that are not in the library
+    /// expansion, which are the unit tests and their helpers.
+    pub fn process_test_file(&mut self, file: syn::File, library: &syn::File) {
+        self.record_extern_crates(&file.items);
+        self.record_imports(&mut Vec::new(), &file.items);
+        let items = test_only_items(file.items, &library.items);
+        for item in items {
+            self.process_item(item);
+        }
+    }
+
     fn process_item(&mut self, item: syn::Item) {
+        self.count_doc_tests(item_attrs(&item));
+        self.process_item_kind(item);
+    }
+
+    fn process_item_kind(&mut self, item: syn::Item) {
         match item {
             syn::Item::Const(item) => self.process_item_const(item),
             syn::Item::Enum(item) => self.process_item_enum(item),
@@ -62,22 +400,37 @@ impl<'a> VersionAnalyzer<'a> {
     }
 
     fn process_item_type(&mut self, item: syn::ItemType) {
+        self.count_generics(&item.generics);
         self.process_type(*item.ty);
     }
 
     fn process_item_trait(&mut self, item: syn::ItemTrait) {
+        self.count_generics(&item.generics);
+
         for item in item.items {
+            match &item {
+                syn::TraitItem::Const(item) => self.count_doc_tests(&item.attrs),
+                syn::TraitItem::Fn(item) => self.count_doc_tests(&item.attrs),
+                syn::TraitItem::Type(item) => self.count_doc_tests(&item.attrs),
+                _ => {}
+            }
+
             match item {
                 syn::TraitItem::Const(const_) => {
+                    self.assoc_consts += 1;
                     self.process_type(const_.ty);
                     if let Some((_, expr)) = const_.default {
-                        self.process_expr(expr);
+                        self.process_const_expr(expr);
                     }
                 }
                 syn::TraitItem::Fn(fn_) => {
+                    let const_ = fn_.sig.constness.is_some() as usize;
+
                     self.process_sig(fn_.sig);
                     if let Some(block) = fn_.default {
+                        self.nested_const += const_;
                         self.process_block(block);
+                        self.nested_const -= const_;
                     }
                 }
                 syn::TraitItem::Type(ty) => {
@@ -93,6 +446,8 @@ impl<'a> VersionAnalyzer<'a> {
     }
 
     fn process_item_struct(&mut self, item: syn::ItemStruct) {
+        self.count_generics(&item.generics);
+
         match item.fields {
             syn::Fields::Named(named) => {
                 for field in named.named {
@@ -110,18 +465,29 @@ impl<'a> VersionAnalyzer<'a> {
 
     fn process_item_static(&mut self, item: syn::ItemStatic) {
         self.process_type(*item.ty);
-        self.process_expr(*item.expr);
+        self.process_const_expr(*item.expr);
     }
 
     fn process_item_const(&mut self, item: syn::ItemConst) {
+        // The test harness turns every `#[test]` fn into a marked const.
+        if item
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("rustc_test_marker"))
+        {
+            self.test_fns += 1;
+        }
+
         self.process_type(*item.ty);
-        self.process_expr(*item.expr);
+        self.process_const_expr(*item.expr);
     }
 
     fn process_item_enum(&mut self, item: syn::ItemEnum) {
+        self.count_generics(&item.generics);
+
         for variant in item.variants {
             if let Some((_, expr)) = variant.discriminant {
-                self.process_expr(expr);
+                self.process_const_expr(expr);
             }
 
             match variant.fields {
@@ -143,6 +509,14 @@ impl<'a> VersionAnalyzer<'a> {
     fn process_item_impl(&mut self, item: syn::ItemImpl) {
         // Can't implement for standard libary types, not needed.
         // self.process_type(*item.self_ty);
+        self.count_generics(&item.generics);
+
+        if let Some((_, path, _)) = &item.trait_ {
+            if self.is_error_trait(path) {
+                self.count_error_impl(&item.items);
+            }
+        }
+
         if let Some((_, path, _)) = item.trait_ {
             self.process_path(path);
         }
@@ -153,12 +527,23 @@ impl<'a> VersionAnalyzer<'a> {
     }
 
     fn process_impl_item(&mut self, item: syn::ImplItem) {
+        match &item {
+            syn::ImplItem::Const(item) => self.count_doc_tests(&item.attrs),
+            syn::ImplItem::Fn(item) => self.count_doc_tests(&item.attrs),
+            syn::ImplItem::Type(item) => self.count_doc_tests(&item.attrs),
+            _ => {}
+        }
+
         match item {
             syn::ImplItem::Const(const_) => {
+                self.assoc_consts += 1;
                 self.process_type(const_.ty);
-                self.process_expr(const_.expr);
+                self.process_const_expr(const_.expr);
             }
             syn::ImplItem::Fn(fun) => {
+                let const_ = fun.sig.constness.is_some() as usize;
+                self.nested_const += const_;
+
                 if fun.sig.unsafety.is_some() {
                     self.nested_unsafe += 1;
                     self.process_sig(fun.sig);
@@ -168,6 +553,8 @@ impl<'a> VersionAnalyzer<'a> {
                     self.process_sig(fun.sig);
                     self.process_block(fun.block);
                 }
+
+                self.nested_const -= const_;
             }
             syn::ImplItem::Type(ty) => {
                 self.process_type(ty.ty);
@@ -179,6 +566,10 @@ impl<'a> VersionAnalyzer<'a> {
     }
 
     fn process_item_fn(&mut self, item: syn::ItemFn) {
+        let property_test_refs = self.property_test_refs;
+        let const_ = item.sig.constness.is_some() as usize;
+        self.nested_const += const_;
+
         if item.sig.unsafety.is_some() {
             self.nested_unsafe += 1;
             self.process_sig(item.sig);
@@ -188,11 +579,23 @@ impl<'a> VersionAnalyzer<'a> {
             self.process_sig(item.sig);
             self.process_block(*item.block);
         }
+
+        self.nested_const -= const_;
+
+        if self.property_test_refs > property_test_refs {
+            self.property_tests += 1;
+        }
     }
 
     fn process_sig(&mut self, sig: syn::Signature) {
         self.total_fns += 1;
         self.async_fns += sig.asyncness.is_some() as usize;
+        self.const_fns += sig.constness.is_some() as usize;
+        self.count_generics(&sig.generics);
+
+        if let syn::ReturnType::Type(_, ty) = &sig.output {
+            self.box_dyn_error_returns += self.contains_box_dyn_error(ty) as usize;
+        }
 
         for arg in sig.inputs {
             if let syn::FnArg::Typed(typed) = arg {
@@ -272,7 +675,10 @@ impl<'a> VersionAnalyzer<'a> {
                 self.process_expr(*closure.body);
             }
             syn::Expr::Const(const_) => {
+                self.inline_const_blocks += 1;
+                self.nested_const += 1;
                 self.process_block(const_.block);
+                self.nested_const -= 1;
             }
             // syn::Expr::Continue(_) => todo!(),
             // syn::Expr::Field(_) => todo!(), FIXME: fields.
@@ -298,6 +704,8 @@ impl<'a> VersionAnalyzer<'a> {
             syn::Expr::Loop(loop_) => self.process_block(loop_.body),
             // syn::Expr::Macro(_) => todo!(),
             syn::Expr::Match(match_) => {
+                self.legacy_try_exprs += is_legacy_try(&match_) as usize;
+
                 self.process_expr(*match_.expr);
                 for arm in match_.arms {
                     if let Some((_, expr)) = arm.guard {
@@ -307,6 +715,18 @@ impl<'a> VersionAnalyzer<'a> {
                 }
             }
             syn::Expr::MethodCall(call) => {
+                if call.method == "unwrap" {
+                    self.unwrap_calls += 1;
+                } else if call.method == "expect" {
+                    self.expect_calls += 1;
+                }
+
+                // This is only a guess, as the receiver could also be a non-std type.
+                let method = call.method.to_string();
+                if let Some(deprecation) = self.version_constructor.get_deprecated_method(&method) {
+                    self.count_deprecated(format!(".{method}()"), deprecation, None);
+                }
+
                 self.process_expr(*call.receiver);
                 for expr in call.args {
                     self.process_expr(expr);
@@ -328,7 +748,7 @@ impl<'a> VersionAnalyzer<'a> {
             syn::Expr::Reference(ref_) => self.process_expr(*ref_.expr),
             syn::Expr::Repeat(repeat) => {
                 self.process_expr(*repeat.expr);
-                self.process_expr(*repeat.len);
+                self.process_const_expr(*repeat.len);
             }
             syn::Expr::Return(ret) => {
                 if let Some(expr) = ret.expr {
@@ -341,7 +761,10 @@ impl<'a> VersionAnalyzer<'a> {
                     self.process_expr(field.expr);
                 }
             }
-            syn::Expr::Try(try_) => self.process_expr(*try_.expr),
+            syn::Expr::Try(try_) => {
+                self.try_exprs += 1;
+                self.process_expr(*try_.expr);
+            }
             syn::Expr::TryBlock(try_block) => self.process_block(try_block.block),
             syn::Expr::Tuple(tuple) => {
                 for expr in tuple.elems {
@@ -368,9 +791,14 @@ impl<'a> VersionAnalyzer<'a> {
         }
     }
 
-    fn process_path(&mut self, path: syn::Path) {
-        // FIXME: Process imports.
+    // Array lengths, discriminants and const/static initializers are evaluated at compile time.
+    fn process_const_expr(&mut self, expr: syn::Expr) {
+        self.nested_const += 1;
+        self.process_expr(expr);
+        self.nested_const -= 1;
+    }
 
+    fn process_path(&mut self, path: syn::Path) {
         let mut relative_path = Vec::new();
         for segment in path.segments {
             relative_path.push(segment.ident.to_string());
@@ -380,20 +808,74 @@ impl<'a> VersionAnalyzer<'a> {
     }
 
     fn process_relative_path(&mut self, relative_path: &[String]) {
-        if let Some(version) = self.version_constructor.get_version(relative_path) {
-            self.count_version(version);
-        } else {
-            // FIXME: This does not work without us keeping track of all imports in here too.
+        match relative_path.first().map(String::as_str) {
+            Some("thiserror") => self.thiserror_refs += 1,
+            Some("anyhow") => self.anyhow_refs += 1,
+            Some("failure") => self.failure_refs += 1,
+            Some("proptest" | "quickcheck") => self.property_test_refs += 1,
+            _ => {}
+        }
+
+        let relative_path = self.resolve_imports(relative_path);
+        if let Some(item) = self.version_constructor.get_item(&relative_path) {
+            self.count_crate(&relative_path);
+            self.count_item(&relative_path, item);
+        }
+    }
+
+    fn count_item(&mut self, path: &[String], item: &'a VersionedItem) {
+        self.resolved_paths += 1;
 
-            // let mut full_path = Vec::with_capacity(self.path.len() + relative_path.len());
-            // full_path.extend_from_slice(&self.path);
-            // full_path.extend_from_slice(relative_path);
+        let api = self
+            .version_constructor
+            .canonical_path(path)
+            .map_or_else(|| path.join("::"), str::to_string);
+        *self.std_apis.entry(api.clone()).or_default() += 1;
 
-            // trace!("checking full path... {full_path:?}");
+        let mut stability = self.version_constructor.effective_stability(path, item);
 
-            // if let Some(version) = self.version_constructor.get_version(&full_path) {
-            //     self.count_version(version);
-            // }
+        if *stability == Stability::Unknown {
+            self.unknown_resolutions += 1;
+
+            match self.unknown_stability {
+                UnknownStability::Ignore => {}
+                UnknownStability::Baseline => self.count_version("1.0.0"),
+                UnknownStability::Inherit => {
+                    stability = self.version_constructor.ancestor_stability(path);
+                }
+            }
+        }
+
+        match stability {
+            Stability::Stable(version) => {
+                // Calls in const contexts need the item to be const stable, which can be much
+                // later.
+                let version = match item.const_since() {
+                    Some(since) if self.nested_const > 0 => {
+                        self.const_api_uses += 1;
+                        since
+                    }
+                    _ => version,
+                };
+
+                self.count_version(version);
+
+                let required = self.std_api_versions.entry(api.clone()).or_default();
+                if parse_version(version) > parse_version(required) {
+                    *required = version.to_string();
+                }
+            }
+            Stability::Unstable(feature) => {
+                *self
+                    .unstable_api_uses
+                    .entry(feature.to_string())
+                    .or_default() += 1;
+            }
+            Stability::Inherited | Stability::Unknown => {}
+        }
+
+        if let Some(deprecation) = item.deprecation() {
+            self.count_deprecated(api, deprecation, item.successor());
         }
     }
 
@@ -440,7 +922,6 @@ impl<'a> VersionAnalyzer<'a> {
         }
     }
 
-    // FIXME: Process imports
     fn process_type(&mut self, ty: syn::Type) {
         match ty {
             syn::Type::Array(array) => self.process_type(*array.elem),
@@ -482,6 +963,61 @@ impl<'a> VersionAnalyzer<'a> {
         }
     }
 
+    // A merged database knows the path of the replacement, which beats the one from the note.
+    fn count_deprecated(
+        &mut self,
+        api: String,
+        deprecation: &Deprecation,
+        successor: Option<&str>,
+    ) {
+        if !deprecation.is_in_effect() {
+            return;
+        }
+
+        self.deprecated_apis
+            .entry(api)
+            .or_insert_with(|| DeprecatedApi {
+                uses: 0,
+                replacement: successor
+                    .or_else(|| deprecation.replacement())
+                    .map(str::to_string),
+            })
+            .uses += 1;
+    }
+
+    fn count_error_impl(&mut self, items: &[syn::ImplItem]) {
+        self.error_impls += 1;
+
+        let has_fn = |name: &str| {
+            items
+                .iter()
+                .any(|item| matches!(item, syn::ImplItem::Fn(fun) if fun.sig.ident == name))
+        };
+
+        self.error_impls_with_source += has_fn("source") as usize;
+        self.error_impls_with_description += has_fn("description") as usize;
+        self.error_impls_with_cause += has_fn("cause") as usize;
+    }
+
+    fn count_generics(&mut self, generics: &syn::Generics) {
+        self.const_generic_params += generics.const_params().count();
+    }
+
+    fn count_doc_tests(&mut self, attrs: &[syn::Attribute]) {
+        let mut in_code_block = false;
+        for line in doc_lines(attrs) {
+            let Some(info) = line.trim_start().strip_prefix("```") else {
+                continue;
+            };
+
+            if !in_code_block && is_doc_test(info) {
+                self.doc_tests += 1;
+            }
+
+            in_code_block = !in_code_block;
+        }
+    }
+
     fn count_expr(&mut self) {
         self.total_exprs += 1;
 
@@ -490,3 +1026,369 @@ impl<'a> VersionAnalyzer<'a> {
         }
     }
 }
+
+/// Collects the gates of `#![feature(...)]` crate attributes in unexpanded source, as the expander
+/// injects gates of its own (like `prelude_import`).
+pub fn feature_gates(attrs: &[syn::Attribute]) -> Vec<String> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("feature"))
+        .filter_map(|attr| {
+            attr.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)
+                .ok()
+        })
+        .flatten()
+        .map(|ident| ident.to_string())
+        .collect()
+}
+
+/// Collects the gates of `#![cfg_attr(nightly, feature(...))]` patterns in unexpanded source.
+pub fn conditional_feature_gates(attrs: &[syn::Attribute]) -> Vec<String> {
+    let mut gates = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("cfg_attr")) {
+        let Ok(metas) = attr.parse_args_with(|input: syn::parse::ParseStream| {
+            input.parse::<syn::Meta>()?;
+            input.parse::<Token![,]>()?;
+            Punctuated::<syn::Meta, Token![,]>::parse_terminated(input)
+        }) else {
+            continue;
+        };
+
+        for meta in metas {
+            let syn::Meta::List(list) = meta else {
+                continue;
+            };
+
+            if !list.path.is_ident("feature") {
+                continue;
+            }
+
+            if let Ok(idents) =
+                list.parse_args_with(Punctuated::<syn::Ident, Token![,]>::parse_terminated)
+            {
+                gates.extend(idents.into_iter().map(|ident| ident.to_string()));
+            }
+        }
+    }
+
+    gates
+}
+
+// The items of a `--tests` expansion that are not in the library expansion. Expansion removes
+// `#[cfg(test)]` from the test modules it keeps, so we can't look for that. Modules in both are
+// compared item by item, as they can contain tests as well.
+fn test_only_items(items: Vec<syn::Item>, library: &[syn::Item]) -> Vec<syn::Item> {
+    let mut library_modules = HashMap::new();
+    let mut library_items = HashSet::new();
+    for item in library {
+        match item {
+            syn::Item::Mod(syn::ItemMod {
+                ident,
+                content: Some((_, items)),
+                ..
+            }) => {
+                library_modules.insert(ident.to_string(), items.as_slice());
+            }
+            item => {
+                library_items.insert(item.to_token_stream().to_string());
+            }
+        }
+    }
+
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            syn::Item::Mod(mut module)
+                if library_modules.contains_key(&module.ident.to_string()) =>
+            {
+                let (brace, items) = module.content.take()?;
+                let items = test_only_items(items, library_modules[&module.ident.to_string()]);
+                module.content = Some((brace, items));
+                Some(syn::Item::Mod(module))
+            }
+            // The harness generates a `main` that runs all tests.
+            syn::Item::Fn(item)
+                if item
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("rustc_main")) =>
+            {
+                None
+            }
+            item => (!library_items.contains(&item.to_token_stream().to_string())).then_some(item),
+        })
+        .collect()
+}
+
+// Keywords that can precede a `!` expression, like `if !(a && b)`.
+const KEYWORDS: &[&str] = &[
+    "if", "while", "match", "return", "in", "else", "let", "mut", "break", "yield",
+];
+
+// Finds `path::to::name!(...)` invocations, including those nested inside other macros.
+fn find_macro_invocations(tokens: TokenStream, invocations: &mut Vec<Vec<String>>) {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+    let mut i = 0;
+    while i < tokens.len() {
+        // Skip items behind `#[cfg(test)]`, up to their body or terminating semicolon.
+        if is_cfg_test_attr(&tokens[i..]) {
+            i += 2;
+            while i < tokens.len() {
+                i += 1;
+                match &tokens[i - 1] {
+                    TokenTree::Group(group) if group.delimiter() == Delimiter::Brace => break,
+                    TokenTree::Punct(punct) if punct.as_char() == ';' => break,
+                    _ => {}
+                }
+            }
+
+            continue;
+        }
+
+        match &tokens[i] {
+            TokenTree::Group(group) => find_macro_invocations(group.stream(), invocations),
+            TokenTree::Punct(punct) if punct.as_char() == '!' && i > 0 => {
+                let (TokenTree::Ident(name), Some(TokenTree::Group(_))) =
+                    (&tokens[i - 1], tokens.get(i + 1))
+                else {
+                    i += 1;
+                    continue;
+                };
+
+                if KEYWORDS.contains(&name.to_string().as_str()) {
+                    i += 1;
+                    continue;
+                }
+
+                let mut path = vec![name.to_string()];
+                let mut start = i - 1;
+                while start >= 3 {
+                    let (TokenTree::Ident(segment), TokenTree::Punct(a), TokenTree::Punct(b)) =
+                        (&tokens[start - 3], &tokens[start - 2], &tokens[start - 1])
+                    else {
+                        break;
+                    };
+
+                    if a.as_char() != ':' || a.spacing() != Spacing::Joint || b.as_char() != ':' {
+                        break;
+                    }
+
+                    path.insert(0, segment.to_string());
+                    start -= 3;
+                }
+
+                invocations.push(path);
+            }
+            _ => {}
+        }
+
+        i += 1;
+    }
+}
+
+/// Collects the names of the `macro_rules!` macros defined in source, wherever they are.
+pub fn macro_definitions(source: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Ok(tokens) = source.parse::<TokenStream>() {
+        find_macro_definitions(tokens, &mut names);
+    }
+
+    names
+}
+
+fn find_macro_definitions(tokens: TokenStream, names: &mut Vec<String>) {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Group(group) => find_macro_definitions(group.stream(), names),
+            TokenTree::Ident(ident) if ident == "macro_rules" => {
+                if let (Some(TokenTree::Punct(punct)), Some(TokenTree::Ident(name))) =
+                    (tokens.get(i + 1), tokens.get(i + 2))
+                {
+                    if punct.as_char() == '!' {
+                        names.push(name.to_string());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The crates imported with `#[macro_use] extern crate` at the crate root, other than std ones.
+pub fn macro_use_crates(items: &[syn::Item]) -> Vec<String> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::ExternCrate(item) => Some(item),
+            _ => None,
+        })
+        .filter(|item| {
+            item.attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("macro_use"))
+        })
+        .filter(|item| !CRATES.iter().any(|crate_| item.ident == crate_))
+        .map(|item| item.ident.to_string())
+        .collect()
+}
+
+/// Collects the names of the macros a crate exports, both `#[macro_export]` ones and function-like
+/// procedural macros.
+pub fn exported_macros(items: &[syn::Item], names: &mut Vec<String>) {
+    for item in items {
+        match item {
+            syn::Item::Macro(item)
+                if item
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("macro_export")) =>
+            {
+                names.extend(item.ident.as_ref().map(|ident| ident.to_string()));
+            }
+            syn::Item::Fn(item)
+                if item
+                    .attrs
+                    .iter()
+                    .any(|attr| attr.path().is_ident("proc_macro")) =>
+            {
+                names.push(item.sig.ident.to_string());
+            }
+            syn::Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    exported_macros(items, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+pub fn is_cfg_test_attr(tokens: &[TokenTree]) -> bool {
+    let [TokenTree::Punct(punct), TokenTree::Group(group), ..] = tokens else {
+        return false;
+    };
+
+    punct.as_char() == '#'
+        && group.delimiter() == Delimiter::Bracket
+        && group.stream().to_string().replace(' ', "") == "cfg(test)"
+}
+
+pub fn item_attrs(item: &syn::Item) -> &[syn::Attribute] {
+    match item {
+        syn::Item::Const(item) => &item.attrs,
+        syn::Item::Enum(item) => &item.attrs,
+        syn::Item::ExternCrate(item) => &item.attrs,
+        syn::Item::Fn(item) => &item.attrs,
+        syn::Item::ForeignMod(item) => &item.attrs,
+        syn::Item::Impl(item) => &item.attrs,
+        syn::Item::Macro(item) => &item.attrs,
+        syn::Item::Mod(item) => &item.attrs,
+        syn::Item::Static(item) => &item.attrs,
+        syn::Item::Struct(item) => &item.attrs,
+        syn::Item::Trait(item) => &item.attrs,
+        syn::Item::TraitAlias(item) => &item.attrs,
+        syn::Item::Type(item) => &item.attrs,
+        syn::Item::Union(item) => &item.attrs,
+        syn::Item::Use(item) => &item.attrs,
+        _ => &[],
+    }
+}
+
+pub fn is_cfg_test(attrs: &[syn::Attribute]) -> bool {
+    attrs.iter().any(|attr| {
+        attr.path().is_ident("cfg")
+            && attr
+                .parse_args::<syn::Ident>()
+                .is_ok_and(|ident| ident == "test")
+    })
+}
+
+fn doc_lines(attrs: &[syn::Attribute]) -> Vec<String> {
+    let mut lines = Vec::new();
+    for attr in attrs {
+        let syn::Meta::NameValue(meta) = &attr.meta else {
+            continue;
+        };
+
+        if !meta.path.is_ident("doc") {
+            continue;
+        }
+
+        if let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(doc),
+            ..
+        }) = &meta.value
+        {
+            lines.extend(doc.value().lines().map(str::to_string));
+        }
+    }
+
+    lines
+}
+
+// Rustdoc runs a code block unless it is ignored or written in another language.
+fn is_doc_test(info: &str) -> bool {
+    info.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|token| !token.is_empty())
+        .all(|token| {
+            matches!(
+                token,
+                "rust" | "should_panic" | "no_run" | "compile_fail" | "test_harness"
+            ) || token.starts_with("edition")
+                || token.starts_with("E0")
+        })
+}
+
+fn path_ends_with(path: &syn::Path, suffix: &[&str]) -> bool {
+    path.segments.len() >= suffix.len()
+        && path
+            .segments
+            .iter()
+            .rev()
+            .zip(suffix.iter().rev())
+            .all(|(segment, name)| segment.ident == name)
+}
+
+// `try!(expr)` expands to `match expr { Ok(val) => val, Err(err) => return Err(From::from(err)) }`.
+fn is_legacy_try(match_: &syn::ExprMatch) -> bool {
+    match_.arms.len() == 2
+        && match_.arms.iter().any(|arm| {
+            let syn::Pat::TupleStruct(pat) = &arm.pat else {
+                return false;
+            };
+
+            path_ends_with(&pat.path, &["Err"]) && is_return_err_from(&arm.body)
+        })
+}
+
+fn is_return_err_from(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Block(block) => match block.block.stmts.as_slice() {
+            [syn::Stmt::Expr(expr, _)] => is_return_err_from(expr),
+            _ => false,
+        },
+        syn::Expr::Return(ret) => {
+            let Some(syn::Expr::Call(call)) = ret.expr.as_deref() else {
+                return false;
+            };
+
+            let syn::Expr::Path(func) = call.func.as_ref() else {
+                return false;
+            };
+
+            let Some(syn::Expr::Call(inner)) = call.args.first() else {
+                return false;
+            };
+
+            let syn::Expr::Path(from) = inner.func.as_ref() else {
+                return false;
+            };
+
+            path_ends_with(&func.path, &["Err"]) && path_ends_with(&from.path, &["From", "from"])
+        }
+        _ => false,
+    }
+}
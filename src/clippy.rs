@@ -0,0 +1,276 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{ensure, Context};
+use log::warn;
+use once_cell::sync::Lazy;
+use serde::{de::IgnoredAny, Deserialize};
+
+use crate::std_versions::toolchain_version;
+
+/// The clippy groups we report separately.
+pub const GROUPS: &[&str] = &[
+    "style",
+    "complexity",
+    "perf",
+    "correctness",
+    "suspicious",
+    "pedantic",
+];
+
+/// Lints that suggest newer std APIs or syntax, but only fire when the MSRV allows them.
+pub const MODERNIZATION_LINTS: &[&str] = &[
+    "cast_abs_to_unsigned",
+    "legacy_numeric_constants",
+    "manual_abs_diff",
+    "manual_bits",
+    "manual_clamp",
+    "manual_div_ceil",
+    "manual_hash_one",
+    "manual_inspect",
+    "manual_is_ascii_check",
+    "manual_is_variant_and",
+    "manual_let_else",
+    "manual_midpoint",
+    "manual_pattern_char_comparison",
+    "manual_range_contains",
+    "manual_rem_euclid",
+    "manual_repeat_n",
+    "manual_retain",
+    "manual_split_once",
+    "manual_str_repeat",
+    "manual_strip",
+    "mem_replace_with_default",
+    "option_as_ref_deref",
+    "uninlined_format_args",
+];
+
+static LINT_GROUPS: Lazy<HashMap<String, String>> = Lazy::new(|| {
+    load_lint_groups().unwrap_or_else(|err| {
+        warn!("could not load clippy lint groups: {err:#}");
+        HashMap::new()
+    })
+});
+
+#[derive(Deserialize)]
+struct CargoMessage {
+    reason: String,
+    manifest_path: Option<PathBuf>,
+    message: Option<Diagnostic>,
+}
+
+#[derive(Deserialize)]
+struct Diagnostic {
+    level: String,
+    code: Option<DiagnosticCode>,
+    spans: Vec<IgnoredAny>,
+}
+
+#[derive(Deserialize)]
+struct DiagnosticCode {
+    code: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ClippyReport {
+    /// The warnings cargo reports with the default lints, including rustc's own.
+    pub warnings: usize,
+    pub lints: BTreeMap<String, usize>,
+    pub groups: BTreeMap<&'static str, usize>,
+}
+
+impl ClippyReport {
+    pub fn group(&self, group: &str) -> usize {
+        self.groups.get(group).copied().unwrap_or_default()
+    }
+}
+
+// `clippy-driver -W help` lists each group on a line like `clippy::style  clippy::a, clippy::b`.
+fn load_lint_groups() -> anyhow::Result<HashMap<String, String>> {
+    let help = Command::new("clippy-driver")
+        .args(["-W", "help"])
+        .output()
+        .context("failed to execute clippy-driver")?;
+
+    let out = String::from_utf8(help.stdout)?;
+
+    let mut groups = HashMap::new();
+    for line in out.lines() {
+        let Some((group, lints)) = line.trim().split_once(char::is_whitespace) else {
+            continue;
+        };
+
+        let Some(group) = group.strip_prefix("clippy::") else {
+            continue;
+        };
+
+        if !GROUPS.contains(&group) {
+            continue;
+        }
+
+        for lint in lints.split(',') {
+            // Diagnostics use underscores in lint names, the help output dashes.
+            let lint = lint.trim().trim_start_matches("clippy::").replace('-', "_");
+            groups.insert(lint, group.to_string());
+        }
+    }
+
+    Ok(groups)
+}
+
+/// Counts the clippy warnings in the given package, and breaks them down per lint and group with
+/// pedantic lints enabled.
+pub fn run_clippy(manifest_path: &Path, all_features: bool) -> anyhow::Result<ClippyReport> {
+    let lint_args = ["-W", "clippy::pedantic"].map(str::to_string);
+
+    let mut report = ClippyReport {
+        warnings: count_default_warnings(manifest_path, all_features)?,
+        ..ClippyReport::default()
+    };
+    // Rustc's own warnings have no lint code that belongs to a group.
+    for code in clippy_warnings(manifest_path, all_features, &lint_args, None)?
+        .into_iter()
+        .flatten()
+    {
+        let group = code
+            .strip_prefix("clippy::")
+            .and_then(|lint| LINT_GROUPS.get(lint))
+            .and_then(|group| GROUPS.iter().find(|name| *name == group));
+
+        if let Some(group) = group {
+            *report.groups.entry(group).or_default() += 1;
+        }
+
+        *report.lints.entry(code).or_default() += 1;
+    }
+
+    Ok(report)
+}
+
+/// Counts the places where clippy would suggest a newer API if the crate allowed the latest Rust
+/// version.
+pub fn count_modernization_debt(manifest_path: &Path, all_features: bool) -> anyhow::Result<usize> {
+    // Clippy reads the MSRV from `clippy.toml`, which takes precedence over `rust-version`.
+    let conf_dir = std::env::temp_dir().join("ruvolution-clippy");
+    std::fs::create_dir_all(&conf_dir).context("failed to create clippy config dir")?;
+    std::fs::write(
+        conf_dir.join("clippy.toml"),
+        format!("msrv = \"{}\"\n", toolchain_version(None)?),
+    )
+    .context("failed to write clippy config")?;
+
+    let mut lint_args = vec!["-A".to_string(), "clippy::all".to_string()];
+    for lint in MODERNIZATION_LINTS {
+        lint_args.push("-W".to_string());
+        lint_args.push(format!("clippy::{lint}"));
+    }
+
+    let debt = clippy_warnings(manifest_path, all_features, &lint_args, Some(&conf_dir))?
+        .into_iter()
+        .flatten()
+        .filter(|code| {
+            code.strip_prefix("clippy::")
+                .is_some_and(|lint| MODERNIZATION_LINTS.contains(&lint))
+        })
+        .count();
+
+    Ok(debt)
+}
+
+// Sums up cargo's `warning: `foo` (lib) generated 3 warnings` lines for a plain `cargo clippy`,
+// which is how `clippy_warnings` has always been counted.
+fn count_default_warnings(manifest_path: &Path, all_features: bool) -> anyhow::Result<usize> {
+    let mut clippy = Command::new("cargo");
+    clippy.arg("clippy");
+    if all_features {
+        clippy.arg("--all-features");
+    }
+    let clippy = clippy
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .output()
+        .context("failed to execute cargo clippy")?;
+
+    ensure!(
+        clippy.status.success(),
+        "cargo clippy could not build the crate"
+    );
+
+    let out = String::from_utf8(clippy.stderr)?;
+
+    Ok(out
+        .lines()
+        .filter(|line| line.starts_with("warning: `"))
+        .filter_map(|line| line.split_once(" generated "))
+        .filter_map(|(_, count)| count.split_whitespace().next()?.parse::<usize>().ok())
+        .sum())
+}
+
+// Runs `cargo clippy` and returns the lint code of every warning in the given package.
+fn clippy_warnings(
+    manifest_path: &Path,
+    all_features: bool,
+    lint_args: &[String],
+    conf_dir: Option<&Path>,
+) -> anyhow::Result<Vec<Option<String>>> {
+    let mut clippy = Command::new("cargo");
+    clippy.arg("clippy").arg("--message-format=json");
+    if all_features {
+        clippy.arg("--all-features");
+    }
+    if let Some(conf_dir) = conf_dir {
+        clippy.env("CLIPPY_CONF_DIR", conf_dir);
+    }
+    let clippy = clippy
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .arg("--")
+        .args(lint_args)
+        .output()
+        .context("failed to execute cargo clippy")?;
+
+    // Warnings don't fail the build, so this means clippy could not check the crate.
+    ensure!(
+        clippy.status.success(),
+        "cargo clippy could not build the crate"
+    );
+
+    let out = String::from_utf8(clippy.stdout)?;
+
+    // Path dependencies are compiled (and linted) too, so we only keep our own package.
+    let manifest_path = manifest_path
+        .canonicalize()
+        .context("could not resolve manifest path")?;
+
+    let mut warnings = Vec::new();
+    for line in out.lines() {
+        let Ok(message) = serde_json::from_str::<CargoMessage>(line) else {
+            continue;
+        };
+
+        let Some(diagnostic) = message.message else {
+            continue;
+        };
+
+        let is_own_package = message
+            .manifest_path
+            .and_then(|path| path.canonicalize().ok())
+            .is_some_and(|path| path == manifest_path);
+
+        // Summaries like "3 warnings emitted" have no spans.
+        if message.reason != "compiler-message"
+            || !is_own_package
+            || diagnostic.level != "warning"
+            || diagnostic.spans.is_empty()
+        {
+            continue;
+        }
+
+        warnings.push(diagnostic.code.map(|code| code.code));
+    }
+
+    Ok(warnings)
+}
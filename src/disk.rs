@@ -1,54 +1,211 @@
-use std::{collections::HashMap, path::Path, process::Command};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+    path::{Path, PathBuf},
+    process::Command,
+};
 
-use anyhow::{anyhow, ensure, Context};
-use log::{debug, trace};
-use once_cell::sync::Lazy;
-use regex::Regex;
+use anyhow::{ensure, Context};
+use joinery::JoinableIterator;
+use log::{debug, trace, warn};
+use once_cell::sync::OnceCell;
 use serde::Serialize;
 
 use crate::{
-    analyzer::VersionAnalyzer,
+    analyzer::{
+        conditional_feature_gates, exported_macros, feature_gates, macro_definitions,
+        macro_use_crates, DeprecatedApi, UnknownStability, VersionAnalyzer,
+    },
+    clippy::{count_modernization_debt, run_clippy},
+    idioms::detect_idioms,
+    msrv::used_language_features,
+    releases::days_since_release,
+    source::{load_module_tree, merge_module_tree},
     std_versions::{load_version_constructor, VersionConstructor},
 };
 
-static VERSION_CONSTRUCTOR: Lazy<VersionConstructor> =
-    Lazy::new(|| load_version_constructor().expect("could not process std versions"));
+static VERSION_CONSTRUCTOR: OnceCell<VersionConstructor> = OnceCell::new();
+
+pub struct AnalysisOptions {
+    pub all_features: bool,
+    pub unknown_stability: UnknownStability,
+    /// Location of the std stability database cache.
+    pub cache: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureCategory {
+    Download,
+    DependencyResolution,
+    MissingSystemLibrary,
+    UnsupportedToolchain,
+    ProcMacroPanic,
+    CompileError,
+    ExpandedParse,
+    Other,
+}
 
-static WARNING_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new("^warning: `[A-Za-z_-]+` \\(\\w+\\) generated (\\d+) warning").unwrap()
-});
+/// Why a crate version could not be analyzed, kept in the error chain for the results file.
+#[derive(Debug)]
+pub struct AnalysisFailure {
+    pub category: FailureCategory,
+    pub message: String,
+}
 
-#[derive(Debug, Serialize)]
+impl fmt::Display for AnalysisFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.category, self.message)
+    }
+}
+
+impl std::error::Error for AnalysisFailure {}
+
+impl AnalysisFailure {
+    // Failures we could classify are somewhere in the error chain, everything else is `Other`.
+    pub fn from_error(err: &anyhow::Error) -> AnalysisFailure {
+        match err
+            .chain()
+            .find_map(|err| err.downcast_ref::<AnalysisFailure>())
+        {
+            Some(failure) => AnalysisFailure {
+                category: failure.category,
+                message: failure.message.clone(),
+            },
+            None => AnalysisFailure {
+                category: FailureCategory::Other,
+                message: format!("{err:#}"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CrateInfo {
     pub name: String,
     pub version: String,
     pub published_at: i64,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Default, Serialize)]
 pub struct Stats {
     // Ideally we would use a CrateInfo here, but csv doesn't support flatten.
     pub name: String,
     pub version: String,
     pub published_at: i64,
 
+    // Set when we fell back to the unexpanded source, which is less accurate.
+    #[serde(skip)]
+    pub expand_failure: Option<AnalysisFailure>,
+
     pub edition: usize,
+    pub extern_crates: usize,
+    pub macro_use_imports: usize,
+    pub bare_trait_objects: usize,
+    pub anonymous_params: usize,
+    pub legacy_range_patterns: usize,
+    pub legacy_absolute_paths: usize,
     pub reported_msrv: Option<usize>,
-    pub version_signature: f32,
+    // The newest release whose std APIs or language features are used.
+    pub estimated_msrv: Option<usize>,
+    // Aggregations of the minor versions of the std APIs used, empty if none were resolved.
+    pub version_signature: Option<f32>,
+    pub version_median: Option<usize>,
+    pub version_p90: Option<usize>,
+    pub version_max: Option<usize>,
+    pub version_mean: Option<f32>,
+    pub distinct_versions: usize,
+    // Days between the newest Rust release whose APIs are used and the publication.
+    pub api_lag_days: Option<i64>,
+    // Days between the release of the reported MSRV and the publication.
+    pub msrv_age_days: Option<i64>,
+    // Fraction of std API uses that were less than a year old when the version was published.
+    pub recent_api_fraction: Option<f32>,
+    // Uses per minor version, written as a column per Rust release.
+    #[serde(skip)]
+    pub version_histogram: BTreeMap<usize, usize>,
+    // What determines the estimated MSRV, for the MSRV timeline.
+    #[serde(skip)]
+    pub std_api_versions: BTreeMap<String, String>,
+    #[serde(skip)]
+    pub language_features: BTreeMap<&'static str, usize>,
+    pub resolved_paths: usize,
+    pub distinct_std_apis: usize,
+    pub unknown_resolutions: usize,
+    pub std_macro_uses: usize,
+    pub no_std: bool,
+    pub std_path_uses: usize,
+    pub core_path_uses: usize,
+    pub alloc_path_uses: usize,
 
     pub unsafe_exprs: usize,
     pub total_exprs: usize,
     pub unsafe_fraction: f32,
 
-    pub clippy_warnings: usize,
-    pub clippy_warnings_per_expr: f32,
+    // Clippy needs a successful build, so these are empty when the crate could not be expanded.
+    pub clippy_warnings: Option<usize>,
+    pub clippy_warnings_per_expr: Option<f32>,
+    pub clippy_style: Option<usize>,
+    pub clippy_complexity: Option<usize>,
+    pub clippy_perf: Option<usize>,
+    pub clippy_correctness: Option<usize>,
+    pub clippy_suspicious: Option<usize>,
+    pub clippy_pedantic: Option<usize>,
+    // Too many to have a column each, so these are written to a separate file.
+    #[serde(skip)]
+    pub clippy_lints: BTreeMap<String, usize>,
+    pub modernization_debt: Option<usize>,
 
     pub async_fns: usize,
     pub total_fns: usize,
     pub async_fraction: f32,
+
+    pub const_fns: usize,
+    pub const_generic_params: usize,
+    pub inline_const_blocks: usize,
+    pub assoc_consts: usize,
+    pub const_api_uses: usize,
+
+    pub try_exprs: usize,
+    pub legacy_try_exprs: usize,
+    pub unwrap_calls: usize,
+    pub expect_calls: usize,
+    pub unwrap_density: f32,
+    pub box_dyn_error_returns: usize,
+    pub error_impls: usize,
+    pub error_impls_with_source: usize,
+    pub error_impls_with_description: usize,
+    pub error_impls_with_cause: usize,
+    pub thiserror_refs: usize,
+    pub anyhow_refs: usize,
+    pub failure_refs: usize,
+
+    pub deprecated_api_uses: usize,
+    pub deprecated_apis: usize,
+    pub deprecated_api_list: String,
+
+    pub feature_gates: usize,
+    pub feature_gate_list: String,
+    pub conditional_feature_gates: usize,
+    pub conditional_feature_gate_list: String,
+    pub unstable_api_uses: usize,
+    pub unstable_api_features: String,
+
+    // Metrics over the test, bench and example targets.
+    pub test_targets: usize,
+    pub test_version_signature: Option<f32>,
+    pub test_unsafe_exprs: usize,
+    pub test_total_exprs: usize,
+    pub test_async_fns: usize,
+    pub test_total_fns: usize,
+    pub test_unwrap_calls: usize,
+    pub test_expect_calls: usize,
+    pub test_fns: usize,
+    pub doc_tests: usize,
+    pub property_tests: usize,
 }
 
-fn rust_version_to_number(version: &str) -> Option<usize> {
+pub fn rust_version_to_number(version: &str) -> Option<usize> {
     version
         .split('.')
         .nth(1)
@@ -63,9 +220,9 @@ fn edition_id(edition: cargo_toml::Edition) -> usize {
     }
 }
 
-fn normalize_versions(versions: &HashMap<String, usize>) -> f32 {
+fn normalize_versions(versions: &HashMap<String, usize>) -> Option<f32> {
     if versions.is_empty() {
-        return 1.0;
+        return None;
     }
 
     let max = versions.values().max().copied().unwrap_or(1) as f32;
@@ -84,68 +241,355 @@ fn normalize_versions(versions: &HashMap<String, usize>) -> f32 {
         weight_acc += weight;
     }
 
-    acc / weight_acc
+    // With a single use of each version, all weights are zero, so every version counts the same.
+    if weight_acc > 0.0 {
+        Some(acc / weight_acc)
+    } else {
+        version_mean(&minor_version_counts(versions))
+    }
 }
 
-fn count_clippy_warnings(manifest_path: &Path, all_features: bool) -> anyhow::Result<usize> {
-    let mut clippy = Command::new("cargo");
-    clippy.arg("clippy");
-    if all_features {
-        clippy.arg("--all-features");
+// Uses per minor version, sorted by version.
+fn minor_version_counts(versions: &HashMap<String, usize>) -> BTreeMap<usize, usize> {
+    let mut counts = BTreeMap::new();
+    for (version, amount) in versions {
+        if let Some(version_number) = rust_version_to_number(version) {
+            *counts.entry(version_number).or_default() += amount;
+        }
     }
-    let clippy = clippy
-        .arg("--manifest-path")
-        .arg(manifest_path)
-        .output()
-        .context("failed to execute cargo clippy")?;
 
-    let out = String::from_utf8(clippy.stderr)?;
+    counts
+}
+
+// The minor version below which `fraction` of all uses fall (nearest rank).
+fn version_percentile(counts: &BTreeMap<usize, usize>, fraction: f32) -> Option<usize> {
+    let total = counts.values().sum::<usize>();
+    let rank = ((total as f32 * fraction).ceil() as usize).max(1);
+
+    let mut seen = 0;
+    for (version, amount) in counts {
+        seen += amount;
+        if seen >= rank {
+            return Some(*version);
+        }
+    }
 
-    Ok(out
-        .lines()
-        .filter_map(|line| WARNING_REGEX.captures(line))
-        .filter_map(|captures| captures.get(1))
-        .filter_map(|n| n.as_str().parse::<usize>().ok())
-        .sum())
+    None
 }
 
-pub fn analyze_single(info: CrateInfo, path: &Path, all_features: bool) -> anyhow::Result<Stats> {
-    ensure!(path.is_dir(), "path should be a directory");
+fn recent_api_fraction(counts: &BTreeMap<usize, usize>, published_at: i64) -> Option<f32> {
+    let total = counts.values().sum::<usize>();
+    if total == 0 {
+        return None;
+    }
 
-    debug!("analyzing {} {}..", info.name, info.version);
+    // A release we don't know the date of would silently count as old, so we give up instead.
+    let mut recent = 0;
+    for (version, amount) in counts {
+        if days_since_release(*version, published_at)? < 365 {
+            recent += amount;
+        }
+    }
 
-    let manifest_path = path.join("Cargo.toml");
+    Some(recent as f32 / total as f32)
+}
 
-    trace!("expanding code...");
+fn version_mean(counts: &BTreeMap<usize, usize>) -> Option<f32> {
+    let total = counts.values().sum::<usize>();
+    if total == 0 {
+        return None;
+    }
+
+    let sum = counts
+        .iter()
+        .map(|(version, amount)| version * amount)
+        .sum::<usize>();
+    Some(sum as f32 / total as f32)
+}
+
+// Formats as `std::mem::uninitialized (MaybeUninit); .description() (to_string)`.
+fn format_deprecated_apis(apis: &HashMap<String, DeprecatedApi>) -> String {
+    let mut apis = apis.iter().collect::<Vec<_>>();
+    apis.sort_by_key(|(name, _)| name.as_str());
+
+    apis.into_iter()
+        .map(|(name, api)| match &api.replacement {
+            Some(replacement) => format!("{name} ({replacement})"),
+            None => name.clone(),
+        })
+        .join_with("; ")
+        .to_string()
+}
+
+fn crate_root(path: &Path, manifest: &cargo_toml::Manifest) -> Option<PathBuf> {
+    let product = manifest.lib.as_ref().or(manifest.bin.first())?;
+    Some(path.join(product.path.as_ref()?))
+}
+
+fn expand(manifest_path: &Path, all_features: bool, target: &[&str]) -> anyhow::Result<syn::File> {
     let mut expand = Command::new("cargo");
-    expand.arg("expand");
+    expand.arg("expand").args(target);
     if all_features {
         expand.arg("--all-features");
     }
     let expand = expand
         .arg("--manifest-path")
-        .arg(&manifest_path)
+        .arg(manifest_path)
         .output()
         .context("failed to execute cargo-expand")?;
 
     if !expand.status.success() {
         let error = String::from_utf8(expand.stderr)?;
-        let concise_error = error.lines().last().context("no last error line")?;
-        return Err(anyhow!("{}", concise_error)).context("could not expand crate");
+        let concise_error = error
+            .lines()
+            .find(|line| line.starts_with("error"))
+            .or(error.lines().last())
+            .context("no last error line")?;
+
+        return Err(AnalysisFailure {
+            category: classify_failure(&error),
+            message: concise_error.to_string(),
+        })
+        .context("could not expand crate");
     }
 
     let expanded_source_code = String::from_utf8(expand.stdout)?;
 
-    let file: syn::File =
-        syn::parse_str(&expanded_source_code).context("could not parse expanded source code")?;
+    syn::parse_str(&expanded_source_code)
+        .map_err(|err| AnalysisFailure {
+            category: FailureCategory::ExpandedParse,
+            message: err.to_string(),
+        })
+        .context("could not parse expanded source code")
+}
 
-    trace!("analyzing versions...");
-    let mut version_analyzer = VersionAnalyzer::new(&VERSION_CONSTRUCTOR);
-    version_analyzer.process_file(file);
+// Finds the macros exported by the given dependencies in their source, as resolved by cargo.
+fn dependency_macros(manifest_path: &Path, crates: &[String]) -> anyhow::Result<Vec<String>> {
+    let metadata = Command::new("cargo")
+        .args(["metadata", "--format-version", "1", "--manifest-path"])
+        .arg(manifest_path)
+        .output()
+        .context("failed to execute cargo-metadata")?;
+    ensure!(metadata.status.success(), "could not resolve dependencies");
+
+    let metadata: serde_json::Value =
+        serde_json::from_slice(&metadata.stdout).context("could not parse cargo metadata")?;
+    let packages = metadata["packages"]
+        .as_array()
+        .context("cargo metadata has no packages")?;
+
+    let mut names = Vec::new();
+    for package in packages {
+        let Some(name) = package["name"].as_str() else {
+            continue;
+        };
+
+        if !crates
+            .iter()
+            .any(|crate_| *crate_ == name.replace('-', "_"))
+        {
+            continue;
+        }
+
+        let targets = package["targets"].as_array().into_iter().flatten();
+        for target in targets {
+            let kinds = target["kind"].as_array().into_iter().flatten();
+            if !kinds
+                .filter_map(|kind| kind.as_str())
+                .any(|kind| kind == "lib" || kind == "proc-macro")
+            {
+                continue;
+            }
+
+            let Some(root) = target["src_path"].as_str() else {
+                continue;
+            };
+
+            for source in load_module_tree(Path::new(root))? {
+                if let Some(file) = &source.file {
+                    exported_macros(&file.items, &mut names);
+                }
+            }
+        }
+    }
+
+    Ok(names)
+}
+
+// Guesses why a build failed from cargo's output. The order matters, as e.g. a missing
+// system library also shows up as a failed build script.
+fn classify_failure(stderr: &str) -> FailureCategory {
+    let contains_any = |patterns: &[&str]| patterns.iter().any(|pattern| stderr.contains(pattern));
+
+    if contains_any(&[
+        "failed to select a version",
+        "no matching package named",
+        "failed to load source for dependency",
+        "failed to get `",
+        "failed to download",
+        "checksum for",
+    ]) {
+        FailureCategory::DependencyResolution
+    } else if contains_any(&[
+        "pkg-config",
+        "Could not find system library",
+        "unable to find library",
+        "cannot find -l",
+        "fatal error:",
+    ]) {
+        FailureCategory::MissingSystemLibrary
+    } else if contains_any(&[
+        "is not supported by the following package",
+        "requires rustc",
+        "may not be used on the stable release channel",
+        "error[E0554]",
+        "error[E0658]",
+        "feature `edition",
+        "this version of Cargo is older",
+    ]) {
+        FailureCategory::UnsupportedToolchain
+    } else if contains_any(&["proc macro panicked", "proc-macro derive panicked"]) {
+        FailureCategory::ProcMacroPanic
+    } else if stderr.contains("error[E") || stderr.contains("could not compile") {
+        FailureCategory::CompileError
+    } else {
+        FailureCategory::Other
+    }
+}
+
+fn analyze_test_targets(
+    manifest_path: &Path,
+    manifest: &cargo_toml::Manifest,
+    all_features: bool,
+    library: &syn::File,
+    test_analyzer: &mut VersionAnalyzer,
+) -> usize {
+    let mut test_targets = 0;
+
+    // Unit tests live next to the library code, so we look at what `--tests` adds to it.
+    match expand(manifest_path, all_features, &["--tests"]) {
+        Ok(file) => {
+            test_analyzer.process_test_file(file, library);
+            test_targets += 1;
+        }
+        Err(err) => warn!("could not expand unit tests: {err:#}"),
+    }
+
+    let targets = [
+        ("--test", &manifest.test),
+        ("--bench", &manifest.bench),
+        ("--example", &manifest.example),
+    ];
+
+    for (kind, products) in targets {
+        for name in products.iter().filter_map(|product| product.name.as_ref()) {
+            match expand(manifest_path, all_features, &[kind, name]) {
+                Ok(file) => {
+                    test_analyzer.process_file(file);
+                    test_targets += 1;
+                }
+                Err(err) => warn!("could not expand {kind} {name}: {err:#}"),
+            }
+        }
+    }
+
+    test_targets
+}
+
+pub fn analyze_single(
+    info: CrateInfo,
+    path: &Path,
+    options: &AnalysisOptions,
+) -> anyhow::Result<Stats> {
+    ensure!(path.is_dir(), "path should be a directory");
+
+    debug!("analyzing {} {}..", info.name, info.version);
+
+    let manifest_path = path.join("Cargo.toml");
 
     let manifest =
         cargo_toml::Manifest::from_path(&manifest_path).context("could not read manifest")?;
 
+    // Some things are gone after expansion, so we also look at the original source.
+    trace!("reading unexpanded source...");
+    let sources = crate_root(path, &manifest)
+        .context("could not find crate root")
+        .and_then(|root| load_module_tree(&root))
+        .unwrap_or_else(|err| {
+            warn!("could not read unexpanded source: {err:#}");
+            Vec::new()
+        });
+
+    trace!("expanding code...");
+    let (file, expand_failure) = match expand(&manifest_path, options.all_features, &[]) {
+        Ok(file) => (file, None),
+        Err(err) => {
+            // Old versions often don't build anymore, but we can still look at their source.
+            let Ok(file) = merge_module_tree(&sources) else {
+                return Err(err);
+            };
+
+            warn!("falling back to unexpanded source: {err:#}");
+            (file, Some(AnalysisFailure::from_error(&err)))
+        }
+    };
+
+    let version_constructor = VERSION_CONSTRUCTOR.get_or_init(|| {
+        load_version_constructor(&options.cache).expect("could not process std versions")
+    });
+
+    trace!("analyzing versions...");
+    let mut version_analyzer = VersionAnalyzer::new(version_constructor, options.unknown_stability);
+    // The unit tests are found by comparing against the expanded library, so we keep it around.
+    let library = expand_failure.is_none().then(|| file.clone());
+    version_analyzer.process_file(file);
+
+    let root = sources.first().and_then(|root| root.file.as_ref());
+    let root_attrs = root.map(|root| root.attrs.as_slice()).unwrap_or_default();
+    let feature_gates: BTreeSet<_> = feature_gates(root_attrs).into_iter().collect();
+    let conditional_feature_gates = conditional_feature_gates(root_attrs);
+
+    // Bare macro names may refer to the crate's own macros or those of its dependencies.
+    let mut local_macros = sources
+        .iter()
+        .flat_map(|source| macro_definitions(&source.source))
+        .collect::<Vec<_>>();
+    let macro_use_crates = root
+        .map(|root| macro_use_crates(&root.items))
+        .unwrap_or_default();
+    if !macro_use_crates.is_empty() {
+        match dependency_macros(&manifest_path, &macro_use_crates) {
+            Ok(names) => local_macros.extend(names),
+            Err(err) => warn!("could not find the macros of `#[macro_use]` crates: {err:#}"),
+        }
+    }
+    version_analyzer.record_local_macros(local_macros);
+
+    for source in &sources {
+        version_analyzer.process_macro_invocations(&source.module, &source.source);
+    }
+
+    let idioms = detect_idioms(&sources);
+
+    // If the library did not build, its tests, benches and examples won't either.
+    let mut test_analyzer = VersionAnalyzer::new(version_constructor, options.unknown_stability);
+    let test_targets = match &library {
+        Some(library) => {
+            trace!("analyzing test targets...");
+            analyze_test_targets(
+                &manifest_path,
+                &manifest,
+                options.all_features,
+                library,
+                &mut test_analyzer,
+            )
+        }
+        None => {
+            warn!("skipping test targets, as the library could not be expanded");
+            0
+        }
+    };
+
     let package = manifest
         .package
         .context("no `package` header in manifest")?;
@@ -156,39 +600,160 @@ pub fn analyze_single(info: CrateInfo, path: &Path, all_features: bool) -> anyho
         version_analyzer.unsafe_exprs, version_analyzer.total_exprs
     );
 
-    trace!("counting warnings with clippy...");
-    let clippy_warnings = count_clippy_warnings(&manifest_path, all_features)
-        .context("failed to count clippy warnings")?;
+    let (clippy, modernization_debt) = if expand_failure.is_none() {
+        trace!("counting warnings with clippy...");
+        let clippy = match run_clippy(&manifest_path, options.all_features) {
+            Ok(report) => Some(report),
+            Err(err) => {
+                warn!("failed to count clippy warnings: {err:#}");
+                None
+            }
+        };
+
+        trace!("counting modernization debt with clippy...");
+        let modernization_debt =
+            match count_modernization_debt(&manifest_path, options.all_features) {
+                Ok(debt) => Some(debt),
+                Err(err) => {
+                    warn!("failed to count modernization debt: {err:#}");
+                    None
+                }
+            };
+
+        (clippy, modernization_debt)
+    } else {
+        (None, None)
+    };
 
     trace!("finishing up...");
+    let minor_versions = minor_version_counts(&version_analyzer.version_counts);
+    let reported_msrv = package
+        .rust_version
+        .as_ref()
+        .and_then(|v| v.get().ok())
+        .and_then(|v| rust_version_to_number(v));
+    let edition = package
+        .edition
+        .get()
+        .copied()
+        .unwrap_or(cargo_toml::Edition::E2015);
+    let language_features = used_language_features(&version_analyzer, edition);
+
     Ok(Stats {
         name: info.name,
         version: info.version,
         published_at: info.published_at,
 
-        edition: edition_id(
-            package
-                .edition
-                .get()
-                .copied()
-                .unwrap_or(cargo_toml::Edition::E2015),
-        ),
-        reported_msrv: package
-            .rust_version
-            .as_ref()
-            .and_then(|v| v.get().ok())
-            .and_then(|v| rust_version_to_number(v)),
+        expand_failure,
+
+        edition: edition_id(edition),
+        extern_crates: idioms.extern_crates,
+        macro_use_imports: idioms.macro_use_imports,
+        bare_trait_objects: idioms.bare_trait_objects,
+        anonymous_params: idioms.anonymous_params,
+        legacy_range_patterns: idioms.legacy_range_patterns,
+        legacy_absolute_paths: idioms.legacy_absolute_paths,
+        reported_msrv,
+        estimated_msrv: minor_versions
+            .keys()
+            .chain(language_features.values())
+            .max()
+            .copied(),
         version_signature: normalize_versions(&version_analyzer.version_counts),
+        version_median: version_percentile(&minor_versions, 0.5),
+        version_p90: version_percentile(&minor_versions, 0.9),
+        version_max: minor_versions.keys().max().copied(),
+        version_mean: version_mean(&minor_versions),
+        distinct_versions: minor_versions.len(),
+        api_lag_days: minor_versions
+            .keys()
+            .max()
+            .and_then(|newest| days_since_release(*newest, info.published_at)),
+        msrv_age_days: reported_msrv.and_then(|msrv| days_since_release(msrv, info.published_at)),
+        recent_api_fraction: recent_api_fraction(&minor_versions, info.published_at),
+        version_histogram: minor_versions,
+        std_api_versions: version_analyzer.std_api_versions.clone(),
+        language_features,
+        resolved_paths: version_analyzer.resolved_paths,
+        distinct_std_apis: version_analyzer.std_apis.len(),
+        unknown_resolutions: version_analyzer.unknown_resolutions,
+        std_macro_uses: version_analyzer.std_macro_uses,
+        no_std: version_analyzer.no_std,
+        std_path_uses: version_analyzer.std_path_uses,
+        core_path_uses: version_analyzer.core_path_uses,
+        alloc_path_uses: version_analyzer.alloc_path_uses,
 
         unsafe_exprs: version_analyzer.unsafe_exprs,
         total_exprs: version_analyzer.total_exprs,
         unsafe_fraction: version_analyzer.unsafe_exprs as f32 / version_analyzer.total_exprs as f32,
 
-        clippy_warnings,
-        clippy_warnings_per_expr: clippy_warnings as f32 / version_analyzer.total_exprs as f32,
+        clippy_warnings: clippy.as_ref().map(|clippy| clippy.warnings),
+        clippy_warnings_per_expr: clippy
+            .as_ref()
+            .map(|clippy| clippy.warnings as f32 / version_analyzer.total_exprs as f32),
+        clippy_style: clippy.as_ref().map(|clippy| clippy.group("style")),
+        clippy_complexity: clippy.as_ref().map(|clippy| clippy.group("complexity")),
+        clippy_perf: clippy.as_ref().map(|clippy| clippy.group("perf")),
+        clippy_correctness: clippy.as_ref().map(|clippy| clippy.group("correctness")),
+        clippy_suspicious: clippy.as_ref().map(|clippy| clippy.group("suspicious")),
+        clippy_pedantic: clippy.as_ref().map(|clippy| clippy.group("pedantic")),
+        clippy_lints: clippy.map(|clippy| clippy.lints).unwrap_or_default(),
+        modernization_debt,
 
         async_fns: version_analyzer.async_fns,
         total_fns: version_analyzer.total_fns,
         async_fraction: version_analyzer.async_fns as f32 / version_analyzer.total_fns as f32,
+
+        const_fns: version_analyzer.const_fns,
+        const_generic_params: version_analyzer.const_generic_params,
+        inline_const_blocks: version_analyzer.inline_const_blocks,
+        assoc_consts: version_analyzer.assoc_consts,
+        const_api_uses: version_analyzer.const_api_uses,
+
+        try_exprs: version_analyzer.try_exprs,
+        legacy_try_exprs: version_analyzer.legacy_try_exprs,
+        unwrap_calls: version_analyzer.unwrap_calls,
+        expect_calls: version_analyzer.expect_calls,
+        unwrap_density: (version_analyzer.unwrap_calls + version_analyzer.expect_calls) as f32
+            / version_analyzer.total_exprs as f32,
+        box_dyn_error_returns: version_analyzer.box_dyn_error_returns,
+        error_impls: version_analyzer.error_impls,
+        error_impls_with_source: version_analyzer.error_impls_with_source,
+        error_impls_with_description: version_analyzer.error_impls_with_description,
+        error_impls_with_cause: version_analyzer.error_impls_with_cause,
+        thiserror_refs: version_analyzer.thiserror_refs,
+        anyhow_refs: version_analyzer.anyhow_refs,
+        failure_refs: version_analyzer.failure_refs,
+
+        deprecated_api_uses: version_analyzer
+            .deprecated_apis
+            .values()
+            .map(|api| api.uses)
+            .sum(),
+        deprecated_apis: version_analyzer.deprecated_apis.len(),
+        deprecated_api_list: format_deprecated_apis(&version_analyzer.deprecated_apis),
+
+        feature_gates: feature_gates.len(),
+        feature_gate_list: feature_gates.iter().join_with(", ").to_string(),
+        conditional_feature_gates: conditional_feature_gates.len(),
+        conditional_feature_gate_list: conditional_feature_gates.iter().join_with(", ").to_string(),
+        unstable_api_uses: version_analyzer.unstable_api_uses.values().sum(),
+        unstable_api_features: version_analyzer
+            .unstable_api_uses
+            .keys()
+            .join_with(", ")
+            .to_string(),
+
+        test_targets,
+        test_version_signature: normalize_versions(&test_analyzer.version_counts),
+        test_unsafe_exprs: test_analyzer.unsafe_exprs,
+        test_total_exprs: test_analyzer.total_exprs,
+        test_async_fns: test_analyzer.async_fns,
+        test_total_fns: test_analyzer.total_fns,
+        test_unwrap_calls: test_analyzer.unwrap_calls,
+        test_expect_calls: test_analyzer.expect_calls,
+        test_fns: test_analyzer.test_fns,
+        doc_tests: version_analyzer.doc_tests,
+        property_tests: test_analyzer.property_tests,
     })
 }
@@ -0,0 +1,409 @@
+use std::collections::HashSet;
+
+use proc_macro2::{Delimiter, Spacing, TokenStream, TokenTree};
+use syn::visit::{self, Visit};
+
+use crate::source::SourceFile;
+
+// Crates that are linked implicitly, or still need `extern crate` in newer editions.
+const IMPLICIT_CRATES: &[&str] = &["std", "core", "alloc", "proc_macro", "test", "self"];
+
+// Closure traits, which syn can't parse as bare trait objects (`Box<Fn(u8) -> u8>`).
+const FN_TRAITS: &[&str] = &["Fn", "FnMut", "FnOnce"];
+
+// Std traits that were commonly used as bare trait objects.
+const STD_TRAITS: &[&str] = &[
+    "Any",
+    "BufRead",
+    "Debug",
+    "Display",
+    "DoubleEndedIterator",
+    "ExactSizeIterator",
+    "Future",
+    "Hasher",
+    "Iterator",
+    "Read",
+    "Seek",
+    "ToString",
+    "Write",
+];
+
+const ITEM_KEYWORDS: &[&str] = &[
+    "const", "enum", "fn", "mod", "static", "struct", "trait", "type", "union",
+];
+
+/// Counts of constructs that the 2018 and 2021 editions replaced.
+#[derive(Debug, Default)]
+pub struct IdiomCounts {
+    pub extern_crates: usize,
+    pub macro_use_imports: usize,
+    pub bare_trait_objects: usize,
+    pub anonymous_params: usize,
+    pub legacy_range_patterns: usize,
+    pub legacy_absolute_paths: usize,
+}
+
+/// Detects outdated idioms in the unexpanded source of a crate, with the crate root first.
+pub fn detect_idioms(sources: &[SourceFile]) -> IdiomCounts {
+    let mut counts = IdiomCounts::default();
+
+    // Some of these don't parse with syn, so we look at the tokens first.
+    for source in sources {
+        if let Ok(tokens) = source.source.parse::<TokenStream>() {
+            count_tokens(tokens, &mut counts);
+        }
+    }
+
+    let files = sources
+        .iter()
+        .filter_map(|source| source.file.as_ref())
+        .collect::<Vec<_>>();
+
+    // Trait names can only be told apart from types if we know which traits the crate defines.
+    let mut trait_collector = TraitCollector::default();
+    for file in &files {
+        trait_collector.visit_file(file);
+    }
+
+    let root_items = sources
+        .first()
+        .and_then(|root| root.source.parse::<TokenStream>().ok())
+        .map(root_item_names)
+        .unwrap_or_default();
+
+    for (i, source) in sources.iter().enumerate() {
+        let Some(file) = &source.file else {
+            continue;
+        };
+
+        let mut visitor = IdiomVisitor {
+            counts: &mut counts,
+            traits: &trait_collector.traits,
+            root_items: &root_items,
+            depth: (i > 0) as usize,
+        };
+
+        visitor.visit_file(file);
+    }
+
+    counts
+}
+
+// Old crate roots often don't parse with syn, so we find the item names in the top level tokens.
+fn root_item_names(tokens: TokenStream) -> HashSet<String> {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+    tokens
+        .windows(2)
+        .filter_map(|pair| {
+            let [TokenTree::Ident(keyword), TokenTree::Ident(name)] = pair else {
+                return None;
+            };
+
+            let is_item_keyword = ITEM_KEYWORDS.iter().any(|item| keyword == item);
+            let is_name = !["fn", "unsafe", "extern", "async"]
+                .iter()
+                .any(|modifier| name == modifier);
+
+            (is_item_keyword && is_name).then(|| name.to_string())
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct TraitCollector {
+    traits: HashSet<String>,
+}
+
+impl<'ast> Visit<'ast> for TraitCollector {
+    fn visit_item_trait(&mut self, item: &'ast syn::ItemTrait) {
+        self.traits.insert(item.ident.to_string());
+        visit::visit_item_trait(self, item);
+    }
+}
+
+struct IdiomVisitor<'a> {
+    counts: &'a mut IdiomCounts,
+    traits: &'a HashSet<String>,
+    root_items: &'a HashSet<String>,
+    // Module depth below the crate root.
+    depth: usize,
+}
+
+impl IdiomVisitor<'_> {
+    fn is_trait(&self, path: &syn::Path) -> bool {
+        let Some(last) = path.segments.last() else {
+            return false;
+        };
+
+        // Closure traits are counted in the tokens.
+        STD_TRAITS.iter().any(|name| last.ident == name)
+            || self.traits.contains(&last.ident.to_string())
+    }
+
+    fn is_root_item(&self, ident: &syn::Ident) -> bool {
+        self.root_items.contains(&ident.to_string())
+    }
+}
+
+impl<'ast> Visit<'ast> for IdiomVisitor<'_> {
+    fn visit_item_mod(&mut self, item: &'ast syn::ItemMod) {
+        self.depth += 1;
+        visit::visit_item_mod(self, item);
+        self.depth -= 1;
+    }
+
+    fn visit_item_use(&mut self, item: &'ast syn::ItemUse) {
+        // In 2015, `use` paths outside the crate root are absolute.
+        let first = match &item.tree {
+            syn::UseTree::Path(path) => Some(&path.ident),
+            syn::UseTree::Name(name) => Some(&name.ident),
+            syn::UseTree::Rename(rename) => Some(&rename.ident),
+            _ => None,
+        };
+
+        if let Some(first) = first {
+            if self.depth > 0 && item.leading_colon.is_none() && self.is_root_item(first) {
+                self.counts.legacy_absolute_paths += 1;
+            }
+        }
+
+        visit::visit_item_use(self, item);
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        // `::module::Item` referred to the current crate in 2015, but to an extern crate since
+        // 2018.
+        if let Some(first) = path.segments.first() {
+            if path.leading_colon.is_some() && self.is_root_item(&first.ident) {
+                self.counts.legacy_absolute_paths += 1;
+            }
+        }
+
+        visit::visit_path(self, path);
+    }
+
+    fn visit_type(&mut self, ty: &'ast syn::Type) {
+        match ty {
+            syn::Type::TraitObject(object) if object.dyn_token.is_none() => {
+                self.counts.bare_trait_objects += 1;
+            }
+            syn::Type::Path(path) if path.qself.is_none() && self.is_trait(&path.path) => {
+                self.counts.bare_trait_objects += 1;
+            }
+            _ => {}
+        }
+
+        visit::visit_type(self, ty);
+    }
+}
+
+fn count_tokens(tokens: TokenStream, counts: &mut IdiomCounts) {
+    let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Group(group) => count_tokens(group.stream(), counts),
+            TokenTree::Ident(ident) if ident == "extern" => {
+                let Some(TokenTree::Ident(name)) = tokens.get(i + 2) else {
+                    continue;
+                };
+
+                if !is_ident(tokens.get(i + 1), "crate")
+                    || IMPLICIT_CRATES.iter().any(|crate_| name == crate_)
+                {
+                    continue;
+                }
+
+                counts.extern_crates += 1;
+                counts.macro_use_imports += has_macro_use(&tokens[..i]) as usize;
+            }
+            TokenTree::Ident(ident) if ident == "fn" => {
+                counts.anonymous_params += count_anonymous_params(&tokens[i + 1..]);
+            }
+            TokenTree::Ident(ident) if FN_TRAITS.iter().any(|name| ident == name) => {
+                counts.bare_trait_objects += is_bare_fn_trait(&tokens, i) as usize;
+            }
+            TokenTree::Punct(punct) if punct.as_char() == '.' && is_range_dots(&tokens, i) => {
+                counts.legacy_range_patterns += 1;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn is_ident(token: Option<&TokenTree>, name: &str) -> bool {
+    matches!(token, Some(TokenTree::Ident(ident)) if ident == name)
+}
+
+fn is_punct(token: Option<&TokenTree>, ch: char) -> bool {
+    matches!(token, Some(TokenTree::Punct(punct)) if punct.as_char() == ch)
+}
+
+// `Fn(...)` directly in a type position, like `Box<Fn()>`, `&Fn()`, `&mut Fn()` or `&'a Fn()`.
+fn is_bare_fn_trait(tokens: &[TokenTree], i: usize) -> bool {
+    let Some(TokenTree::Group(group)) = tokens.get(i + 1) else {
+        return false;
+    };
+
+    if group.delimiter() != Delimiter::Parenthesis || i == 0 {
+        return false;
+    }
+
+    let mut before = i - 1;
+    if is_ident(tokens.get(before), "mut") {
+        before = before.saturating_sub(1);
+    } else if before >= 1
+        && matches!(&tokens[before], TokenTree::Ident(_))
+        && is_punct(tokens.get(before - 1), '\'')
+    {
+        before = before.saturating_sub(2);
+    }
+
+    is_punct(tokens.get(before), '<') || is_punct(tokens.get(before), '&')
+}
+
+// Looks back through the attributes right before an `extern crate` for `#[macro_use]`.
+fn has_macro_use(tokens: &[TokenTree]) -> bool {
+    let mut end = tokens.len();
+    while end >= 2 {
+        let (TokenTree::Punct(punct), TokenTree::Group(group)) =
+            (&tokens[end - 2], &tokens[end - 1])
+        else {
+            return false;
+        };
+
+        if punct.as_char() != '#' || group.delimiter() != Delimiter::Bracket {
+            return false;
+        }
+
+        if is_ident(group.stream().into_iter().next().as_ref(), "macro_use") {
+            return true;
+        }
+
+        end -= 2;
+    }
+
+    false
+}
+
+// Counts the parameters without a name in `fn name<...>(...)`, which 2015 allowed in traits.
+fn count_anonymous_params(tokens: &[TokenTree]) -> usize {
+    let Some(TokenTree::Ident(_)) = tokens.first() else {
+        return 0;
+    };
+
+    let mut i = 1;
+    if is_punct(tokens.get(i), '<') {
+        let mut depth = 0;
+        while i < tokens.len() {
+            depth += angle_depth_change(tokens, i);
+            i += 1;
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+
+    let Some(TokenTree::Group(group)) = tokens.get(i) else {
+        return 0;
+    };
+
+    if group.delimiter() != Delimiter::Parenthesis {
+        return 0;
+    }
+
+    let params = group.stream().into_iter().collect::<Vec<_>>();
+
+    let mut anonymous = 0;
+    let mut depth = 0;
+    let mut start = 0;
+    for j in 0..=params.len() {
+        if j < params.len() {
+            depth += angle_depth_change(&params, j);
+            if depth > 0 || !is_punct(params.get(j), ',') {
+                continue;
+            }
+        }
+
+        let param = &params[start..j];
+        start = j + 1;
+
+        // C variadics (`...`) have no name either, but that is not an old idiom.
+        let is_self = param.iter().any(|token| is_ident(Some(token), "self"));
+        let is_variadic = is_punct(param.first(), '.');
+        if !param.is_empty() && !is_self && !is_variadic && !has_type_colon(param) {
+            anonymous += 1;
+        }
+    }
+
+    anonymous
+}
+
+fn angle_depth_change(tokens: &[TokenTree], i: usize) -> isize {
+    let TokenTree::Punct(punct) = &tokens[i] else {
+        return 0;
+    };
+
+    // The `>` in `->` does not close anything.
+    let is_arrow = i > 0
+        && matches!(
+            &tokens[i - 1],
+            TokenTree::Punct(prev) if prev.as_char() == '-' && prev.spacing() == Spacing::Joint
+        );
+
+    match punct.as_char() {
+        '<' => 1,
+        '>' if !is_arrow => -1,
+        _ => 0,
+    }
+}
+
+// A single `:` at the top level, as opposed to the `::` in paths.
+fn has_type_colon(param: &[TokenTree]) -> bool {
+    let mut depth = 0;
+    for (i, token) in param.iter().enumerate() {
+        depth += angle_depth_change(param, i);
+
+        let TokenTree::Punct(punct) = token else {
+            continue;
+        };
+
+        if depth != 0 || punct.as_char() != ':' {
+            continue;
+        }
+
+        let after_colon = i > 0 && is_punct(param.get(i - 1), ':');
+        if punct.spacing() == Spacing::Alone && !after_colon {
+            return true;
+        }
+    }
+
+    false
+}
+
+// `a...b` range patterns, but not the `...` of C variadics, which ends a parameter list.
+fn is_range_dots(tokens: &[TokenTree], i: usize) -> bool {
+    let is_dot = |j: usize, spacing: Spacing| {
+        matches!(
+            tokens.get(j),
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '.' && punct.spacing() == spacing
+        )
+    };
+
+    if !is_dot(i, Spacing::Joint)
+        || !is_dot(i + 1, Spacing::Joint)
+        || !is_dot(i + 2, Spacing::Alone)
+    {
+        return false;
+    }
+
+    // Make sure we count every `...` only once.
+    if i > 0 && is_punct(tokens.get(i - 1), '.') {
+        return false;
+    }
+
+    let before = i.checked_sub(1).and_then(|j| tokens.get(j));
+    let after = tokens.get(i + 3);
+    before.is_some() && !is_punct(before, ',') && after.is_some()
+}
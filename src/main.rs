@@ -1,23 +1,40 @@
 use std::{
+    collections::BTreeSet,
+    fs::File,
     io::Cursor,
     path::{Path, PathBuf},
     time::Duration,
 };
 
+use analyzer::UnknownStability;
 use anyhow::Context;
-use clap::Parser;
+use clap::{error::ErrorKind, CommandFactory, Parser, Subcommand, ValueEnum};
 use crates_io_api::{SyncClient, Version};
-use disk::{analyze_single, CrateInfo, Stats};
+use disk::{analyze_single, AnalysisFailure, AnalysisOptions, CrateInfo, FailureCategory, Stats};
 use flate2::read::GzDecoder;
 use indicatif::{MultiProgress, ProgressBar, ProgressIterator, ProgressStyle};
 use indicatif_log_bridge::LogWrapper;
 use log::{debug, error, trace, LevelFilter};
+use msrv::msrv_timeline;
 use once_cell::sync::Lazy;
+use output::{write_results, VersionResult};
 use reqwest::Url;
+use serde_json::json;
+use std_versions::{
+    build_version_constructor, build_version_constructor_from_rustdoc, is_same_release,
+    load_version_constructor, merge_version_constructors, rust_src_library, CacheMetadata,
+    Stability, VersionConstructor,
+};
 use tar::Archive;
 
 mod analyzer;
+mod clippy;
 mod disk;
+mod idioms;
+mod msrv;
+mod output;
+mod releases;
+mod source;
 mod std_versions;
 
 const TEMP_DIR: &str = ".current_crate";
@@ -32,10 +49,18 @@ static API_CLIENT: Lazy<SyncClient> = Lazy::new(|| {
 });
 
 #[derive(Parser)]
-#[command(version)]
+#[command(
+    version,
+    subcommand_negates_reqs = true,
+    override_usage = "ruvolution [OPTIONS] <CRATE>\n       ruvolution [OPTIONS] <COMMAND>"
+)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Crate name on crates.io to analyze
-    crate_: String,
+    #[arg(required = true)]
+    crate_: Option<String>,
 
     /// Amount of versions to fetch and analyze
     #[arg(short, long, default_value_t = 20)]
@@ -48,12 +73,210 @@ struct Args {
     /// Analyze using only the default crate features
     #[arg(short, long)]
     not_all_features: bool,
+
+    /// How to count std items without a stability attribute
+    #[arg(long, value_enum, default_value_t)]
+    unknown_stability: UnknownStability,
+
+    /// Location of the std stability database, stored as JSON with a `.json` extension and as CBOR
+    /// otherwise
+    #[arg(long, global = true, default_value = "cache.json")]
+    cache: PathBuf,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the std stability database from the rust-src component of a toolchain
+    BuildStdDb {
+        /// Rustup toolchain to use, e.g. `1.75.0` (defaults to the active one)
+        #[arg(short, long)]
+        toolchain: Option<String>,
+
+        /// Path to the `library` directory of a Rust source tree, instead of a toolchain
+        #[arg(short, long, conflicts_with = "toolchain")]
+        library: Option<PathBuf>,
+
+        /// Evaluate `#[cfg]`s for these targets and merge the results, tagging items with their
+        /// targets
+        #[arg(long = "target", conflicts_with = "rustdoc_json")]
+        targets: Vec<String>,
+
+        /// Directory with rustdoc JSON output for `core`, `alloc` and `std`, instead of sources
+        #[arg(long, conflicts_with_all = ["toolchain", "library"])]
+        rustdoc_json: Option<PathBuf>,
+    },
+    /// Merge databases of several Rust releases into one historical database
+    MergeStdDb {
+        /// Database of a release, as `VERSION=PATH` (e.g. `1.40.0=cache-1.40.json`)
+        #[arg(required = true, value_parser = parse_snapshot)]
+        snapshots: Vec<(String, PathBuf)>,
+    },
+    /// Print since when a std path is stable
+    Since {
+        /// Path as it would be written in code, e.g. `std::vec::Vec` or `Option::map`
+        path: String,
+    },
+    /// List everything in the std database that was stabilized in a release
+    List {
+        /// Rust release, e.g. `1.70`
+        #[arg(long)]
+        since: String,
+    },
+    /// Export every path in the std database with its stability
+    Export {
+        #[arg(short, long, value_enum, default_value_t)]
+        format: ExportFormat,
+    },
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+fn parse_snapshot(arg: &str) -> Result<(String, PathBuf), String> {
+    let (version, path) = arg
+        .split_once('=')
+        .ok_or_else(|| format!("expected VERSION=PATH, got `{arg}`"))?;
+
+    Ok((version.to_string(), PathBuf::from(path)))
+}
+
+fn build_std_db(
+    toolchain: Option<&str>,
+    library: Option<PathBuf>,
+    targets: &[String],
+    rustdoc_json: Option<PathBuf>,
+    cache: &Path,
+) -> anyhow::Result<()> {
+    if let Some(dir) = rustdoc_json {
+        debug!(
+            "building std database from rustdoc JSON in {}...",
+            dir.display()
+        );
+        let version_constructor = build_version_constructor_from_rustdoc(&dir)?;
+        return version_constructor.save(cache, CacheMetadata::pinned());
+    }
+
+    // Only a database of the active toolchain can be rebuilt when it changes.
+    let metadata = if toolchain.is_none() && library.is_none() && targets.is_empty() {
+        CacheMetadata::active_toolchain()?
+    } else {
+        CacheMetadata::pinned()
+    };
+
+    let library = match library {
+        Some(library) => library,
+        None => rust_src_library(toolchain)?,
+    };
+
+    debug!("building std database from {}...", library.display());
+    build_version_constructor(&library, toolchain, targets)?.save(cache, metadata)
+}
+
+fn print_since(version_constructor: &VersionConstructor, path: &str) -> anyhow::Result<()> {
+    let mut segments = path.split("::").map(str::to_string).collect::<Vec<_>>();
+    // Macros can be looked up without their `!`, like in a `use`.
+    if version_constructor.get_item(&segments).is_none() {
+        let mut macro_segments = segments.clone();
+        if let Some(name) = macro_segments.last_mut() {
+            name.push('!');
+        }
+
+        if version_constructor.get_item(&macro_segments).is_some() {
+            segments = macro_segments;
+        }
+    }
+
+    let path = segments.join("::");
+    let item = version_constructor
+        .get_item(&segments)
+        .with_context(|| format!("could not resolve {path}"))?;
+
+    match version_constructor.canonical_path(&segments) {
+        Some(canonical) if canonical != path => println!("{path} (re-export of {canonical})"),
+        _ => println!("{path}"),
+    }
+
+    let feature = version_constructor.effective_feature(&segments, item);
+    let feature = feature.map_or(String::new(), |feature| format!(" (feature `{feature}`)"));
+
+    match version_constructor.effective_stability(&segments, item) {
+        Stability::Stable(since) => println!("  stable since {since}{feature}"),
+        Stability::Unstable(_) => println!("  unstable{feature}"),
+        Stability::Inherited | Stability::Unknown => println!("  unknown stability"),
+    }
+
+    if let Some(deprecation) = item.deprecation() {
+        match &deprecation.since {
+            Some(since) => println!("  deprecated since {since}"),
+            None => println!("  deprecated"),
+        }
+    }
+
+    if let Some(since) = item.const_since() {
+        println!("  const since {since}");
+    }
+
+    Ok(())
+}
+
+// Re-exports of the same item are only listed once, by their canonical path.
+fn list_since(version_constructor: &VersionConstructor, version: &str) {
+    let canonical = version_constructor
+        .exported_items()
+        .into_iter()
+        .filter(|exported| {
+            exported.public
+                && exported
+                    .since
+                    .as_deref()
+                    .is_some_and(|since| is_same_release(since, version))
+        })
+        .map(|exported| exported.canonical)
+        .collect::<BTreeSet<_>>();
+
+    for path in canonical {
+        println!("{path}");
+    }
+}
+
+fn export(version_constructor: &VersionConstructor, format: ExportFormat) -> anyhow::Result<()> {
+    let items = version_constructor
+        .exported_items()
+        .into_iter()
+        .filter(|item| item.public)
+        .collect::<Vec<_>>();
+
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for item in items {
+                writer.serialize(item)?;
+            }
+            writer.flush()?;
+        }
+        ExportFormat::Json => serde_json::to_writer_pretty(std::io::stdout(), &items)?,
+    }
+
+    Ok(())
 }
 
-fn analyze_version(version: &Version, all_features: bool) -> anyhow::Result<Stats> {
+fn analyze_version(
+    version: &Version,
+    info: CrateInfo,
+    options: &AnalysisOptions,
+) -> anyhow::Result<Stats> {
     let url = Url::parse("https://crates.io/")?.join(&version.dl_path)?;
     trace!("downloading from {url}...");
-    let res = reqwest::blocking::get(url).and_then(|res| res.bytes())?;
+    let res = reqwest::blocking::get(url)
+        .and_then(|res| res.bytes())
+        .map_err(|err| AnalysisFailure {
+            category: FailureCategory::Download,
+            message: err.to_string(),
+        })?;
 
     trace!("extracting archive...");
     let temp_dir = Path::new(TEMP_DIR);
@@ -63,16 +286,7 @@ fn analyze_version(version: &Version, all_features: bool) -> anyhow::Result<Stat
     let mut archive = Archive::new(decoder);
     archive.unpack(temp_dir).context("failed to unpack")?;
 
-    let stats = analyze_single(
-        CrateInfo {
-            name: version.crate_name.clone(),
-            version: version.num.clone(),
-            published_at: version.created_at.timestamp(),
-        },
-        &crate_dir,
-        all_features,
-    )
-    .context("failed to analyze");
+    let stats = analyze_single(info, &crate_dir, options).context("failed to analyze");
 
     std::fs::remove_dir_all(crate_dir).context("failed to delete temp crate dir")?;
 
@@ -83,8 +297,8 @@ fn analyze_from_crates_io(
     progress: ProgressBar,
     name: &str,
     count: usize,
-    all_features: bool,
-) -> anyhow::Result<Vec<Stats>> {
+    options: &AnalysisOptions,
+) -> anyhow::Result<Vec<VersionResult>> {
     let res = API_CLIENT
         .get_crate(name)
         .context("failed to get crate information from API")?;
@@ -136,27 +350,46 @@ fn analyze_from_crates_io(
         versions.iter().map(|v| &v.num).collect::<Vec<_>>()
     );
 
-    let mut stats = Vec::with_capacity(versions.len());
+    let mut results = Vec::with_capacity(versions.len());
     for version in versions.iter().progress_with(progress.clone()) {
         progress.set_message(version.num.clone());
 
-        let stat = match analyze_version(version, all_features) {
+        let info = CrateInfo {
+            name: version.crate_name.clone(),
+            version: version.num.clone(),
+            published_at: version.created_at.timestamp(),
+        };
+
+        let stat = match analyze_version(version, info.clone(), options) {
             Ok(stat) => stat,
             Err(err) => {
                 error!("could not analyze {name} {}: {err:#}", version.num);
+                results.push(VersionResult::Failed {
+                    info,
+                    failure: AnalysisFailure::from_error(&err),
+                });
                 continue;
             }
         };
 
         debug!("{stat:?}");
-        stats.push(stat);
+        results.push(VersionResult::Analyzed(Box::new(stat)));
     }
 
-    Ok(stats)
+    Ok(results)
 }
 
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
+    // `--cache` can go before or after a subcommand, so clap accepts a crate name with one too.
+    if args.command.is_some() && args.crate_.is_some() {
+        Args::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "a crate name can't be combined with a subcommand",
+            )
+            .exit();
+    }
 
     let multi = MultiProgress::new();
     let style = ProgressStyle::with_template(
@@ -171,39 +404,89 @@ fn main() -> anyhow::Result<()> {
 
     LogWrapper::new(multi.clone(), logger).try_init().unwrap();
 
+    match args.command {
+        Some(Command::BuildStdDb {
+            toolchain,
+            library,
+            targets,
+            rustdoc_json,
+        }) => {
+            return build_std_db(
+                toolchain.as_deref(),
+                library,
+                &targets,
+                rustdoc_json,
+                &args.cache,
+            )
+        }
+        Some(Command::MergeStdDb { snapshots }) => {
+            return merge_version_constructors(&snapshots)?
+                .save(&args.cache, CacheMetadata::pinned());
+        }
+        Some(Command::Since { path }) => {
+            return print_since(&load_version_constructor(&args.cache)?, &path);
+        }
+        Some(Command::List { since }) => {
+            list_since(&load_version_constructor(&args.cache)?, &since);
+            return Ok(());
+        }
+        Some(Command::Export { format }) => {
+            return export(&load_version_constructor(&args.cache)?, format);
+        }
+        None => {}
+    }
+
+    let name = &args
+        .crate_
+        .expect("crate name is required without a subcommand");
+
     // Prepare output file
     let csv_path = args.out_file.unwrap_or_else(|| {
         let out_dir = Path::new(OUT_DIR);
         std::fs::create_dir_all(out_dir).expect("failed to create results dir");
-        out_dir.join(format!("{}.csv", args.crate_))
+        out_dir.join(format!("{name}.csv"))
     });
 
     // Analyze the crate versions
-    let name = &args.crate_;
-
     let progress = multi.add(
         ProgressBar::new(args.versions as u64)
             .with_style(style)
             .with_prefix(name.to_string()),
     );
 
-    let stats = analyze_from_crates_io(
-        progress.clone(),
-        name,
-        args.versions,
-        !args.not_all_features,
-    )?;
+    let options = AnalysisOptions {
+        all_features: !args.not_all_features,
+        unknown_stability: args.unknown_stability,
+        cache: args.cache,
+    };
+
+    let results = analyze_from_crates_io(progress.clone(), name, args.versions, &options)?;
+
+    let stats = results
+        .iter()
+        .filter_map(|result| match result {
+            VersionResult::Analyzed(stat) => Some(stat.as_ref()),
+            VersionResult::Failed { .. } => None,
+        })
+        .collect::<Vec<_>>();
 
     progress.abandon_with_message(format!("analyzed with {} versions", stats.len()));
 
-    // Write results to CSV
-    let mut writer = csv::Writer::from_path(csv_path)?;
+    // Write per-lint clippy counts next to the CSV
+    let lints = stats
+        .iter()
+        .map(|stat| json!({ "version": stat.version, "lints": stat.clippy_lints }))
+        .collect::<Vec<_>>();
 
-    for stat in stats {
-        writer.serialize(stat)?;
-    }
+    let lints_file = File::create(csv_path.with_extension("lints.json"))?;
+    serde_json::to_writer_pretty(lints_file, &lints)?;
 
-    writer.flush()?;
+    // Write what the MSRV went up for next to the CSV
+    let msrv_file = File::create(csv_path.with_extension("msrv.json"))?;
+    serde_json::to_writer_pretty(msrv_file, &msrv_timeline(&stats))?;
+
+    // Write results to CSV
+    write_results(&csv_path, &results)?;
 
     Ok(())
 }
@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::{
+    analyzer::VersionAnalyzer,
+    disk::{rust_version_to_number, Stats},
+};
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MsrvKind {
+    /// The `rust-version` in the manifest.
+    Reported,
+    /// The newest release whose APIs or language features are used.
+    Estimated,
+}
+
+/// A version in which the MSRV went up, with what was first used in it that needs more than
+/// the previous MSRV.
+#[derive(Debug, Serialize)]
+pub struct MsrvBump {
+    pub version: String,
+    pub published_at: i64,
+    pub kind: MsrvKind,
+    pub from: String,
+    pub to: String,
+    pub std_apis: BTreeMap<String, String>,
+    pub language_features: BTreeMap<&'static str, String>,
+}
+
+/// The language features used by a crate, with the minor version that stabilized them.
+pub fn used_language_features(
+    analyzer: &VersionAnalyzer,
+    edition: cargo_toml::Edition,
+) -> BTreeMap<&'static str, usize> {
+    let features = [
+        ("try operator", 13, analyzer.try_exprs),
+        ("associated consts", 20, analyzer.assoc_consts),
+        ("const fn", 31, analyzer.const_fns),
+        (
+            "edition 2018",
+            31,
+            (edition != cargo_toml::Edition::E2015) as usize,
+        ),
+        ("async fn", 39, analyzer.async_fns),
+        ("const generics", 51, analyzer.const_generic_params),
+        (
+            "edition 2021",
+            56,
+            (edition == cargo_toml::Edition::E2021) as usize,
+        ),
+        ("inline const", 79, analyzer.inline_const_blocks),
+    ];
+
+    features
+        .into_iter()
+        .filter(|(_, _, uses)| *uses > 0)
+        .map(|(name, minor, _)| (name, minor))
+        .collect()
+}
+
+/// Every MSRV bump between the analyzed versions of a crate, in order of publication.
+pub fn msrv_timeline(stats: &[&Stats]) -> Vec<MsrvBump> {
+    let mut stats = stats.to_vec();
+    stats.sort_by_key(|stat| stat.published_at);
+
+    let mut bumps = Vec::new();
+    let mut seen_apis = BTreeSet::new();
+    let mut seen_features = BTreeSet::new();
+    let mut reported = None;
+    let mut estimated = None;
+
+    for stat in stats {
+        let msrvs = [
+            (MsrvKind::Reported, reported, stat.reported_msrv),
+            (MsrvKind::Estimated, estimated, stat.estimated_msrv),
+        ];
+
+        for (kind, previous, current) in msrvs {
+            let (Some(previous), Some(current)) = (previous, current) else {
+                continue;
+            };
+
+            if current <= previous {
+                continue;
+            }
+
+            let std_apis = stat
+                .std_api_versions
+                .iter()
+                .filter(|(path, _)| !seen_apis.contains(*path))
+                .filter(|(_, since)| rust_version_to_number(since).is_some_and(|v| v > previous))
+                .map(|(path, since)| (path.clone(), since.clone()))
+                .collect();
+
+            let language_features = stat
+                .language_features
+                .iter()
+                .filter(|(name, minor)| !seen_features.contains(*name) && **minor > previous)
+                .map(|(name, minor)| (*name, format!("1.{minor}")))
+                .collect();
+
+            bumps.push(MsrvBump {
+                version: stat.version.clone(),
+                published_at: stat.published_at,
+                kind,
+                from: format!("1.{previous}"),
+                to: format!("1.{current}"),
+                std_apis,
+                language_features,
+            });
+        }
+
+        seen_apis.extend(stat.std_api_versions.keys().cloned());
+        seen_features.extend(stat.language_features.keys().copied());
+        reported = stat.reported_msrv.or(reported);
+        estimated = stat.estimated_msrv.or(estimated);
+    }
+
+    bumps
+}
@@ -0,0 +1,127 @@
+use std::path::Path;
+
+use anyhow::Context;
+use csv::StringRecord;
+use serde::Serialize;
+
+use crate::{
+    disk::{AnalysisFailure, CrateInfo, FailureCategory, Stats},
+    releases::KNOWN_RELEASES,
+};
+
+// Identity columns at the start of `Stats`, which failed versions also have.
+const INFO_COLUMNS: usize = 3;
+
+// Every release in the calendar and a few after it get a histogram column each, and anything
+// newer is summed up in `rust_1_later`. This does not depend on the crate, so that the CSVs of
+// different crates have the same columns and can be stacked.
+const HISTOGRAM_HEADROOM: usize = 10;
+const HISTOGRAM_RELEASES: usize = KNOWN_RELEASES + HISTOGRAM_HEADROOM;
+
+pub enum VersionResult {
+    Analyzed(Box<Stats>),
+    Failed {
+        info: CrateInfo,
+        failure: AnalysisFailure,
+    },
+}
+
+#[derive(Serialize)]
+struct Status<'a> {
+    status: &'a str,
+    failure_category: Option<FailureCategory>,
+    failure_message: Option<&'a str>,
+}
+
+// Serializes a single value with csv, returning both its header and its values.
+fn to_records<T: Serialize>(value: &T) -> anyhow::Result<(StringRecord, StringRecord)> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.serialize(value)?;
+    let data = writer.into_inner().context("could not serialize record")?;
+
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(data.as_slice());
+
+    let mut records = reader.records();
+    let header = records.next().context("no header record")??;
+    let values = records.next().context("no value record")??;
+
+    Ok((header, values))
+}
+
+/// Writes a row for every attempted version, leaving the metrics of failed versions empty.
+pub fn write_results(path: &Path, results: &[VersionResult]) -> anyhow::Result<()> {
+    let (stats_header, _) = to_records(&Stats::default())?;
+    let (status_header, _) = to_records(&Status {
+        status: "",
+        failure_category: None,
+        failure_message: None,
+    })?;
+
+    let mut writer = csv::Writer::from_path(path)?;
+
+    let mut header = StringRecord::new();
+    header.extend(stats_header.iter().take(INFO_COLUMNS));
+    header.extend(status_header.iter());
+    header.extend(stats_header.iter().skip(INFO_COLUMNS));
+    header.extend((0..HISTOGRAM_RELEASES).map(|minor| format!("rust_1_{minor}")));
+    header.push_field("rust_1_later");
+    writer.write_record(&header)?;
+
+    for result in results {
+        let mut record = StringRecord::new();
+
+        match result {
+            VersionResult::Analyzed(stats) => {
+                let (_, values) = to_records(stats)?;
+                let (_, status) = to_records(&match &stats.expand_failure {
+                    None => Status {
+                        status: "ok",
+                        failure_category: None,
+                        failure_message: None,
+                    },
+                    Some(failure) => Status {
+                        status: "unexpanded",
+                        failure_category: Some(failure.category),
+                        failure_message: Some(&failure.message),
+                    },
+                })?;
+
+                record.extend(values.iter().take(INFO_COLUMNS));
+                record.extend(status.iter());
+                record.extend(values.iter().skip(INFO_COLUMNS));
+                record.extend((0..HISTOGRAM_RELEASES).map(|minor| {
+                    let uses = stats.version_histogram.get(&minor).copied();
+                    uses.unwrap_or_default().to_string()
+                }));
+                let later: usize = stats
+                    .version_histogram
+                    .range(HISTOGRAM_RELEASES..)
+                    .map(|(_, uses)| uses)
+                    .sum();
+                record.push_field(&later.to_string());
+            }
+            VersionResult::Failed { info, failure } => {
+                let (_, values) = to_records(info)?;
+                let (_, status) = to_records(&Status {
+                    status: "failed",
+                    failure_category: Some(failure.category),
+                    failure_message: Some(&failure.message),
+                })?;
+
+                record.extend(values.iter());
+                record.extend(status.iter());
+                record.extend((INFO_COLUMNS..stats_header.len()).map(|_| ""));
+                // The histogram columns and `rust_1_later`.
+                record.extend((0..=HISTOGRAM_RELEASES).map(|_| ""));
+            }
+        }
+
+        writer.write_record(&record)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
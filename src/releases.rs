@@ -0,0 +1,135 @@
+use chrono::NaiveDate;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Release dates by minor version, to relate the std APIs a crate uses to when it was published.
+// Since 1.1, there has been a release every six weeks.
+const RELEASE_DATES: &[(usize, &str)] = &[
+    (0, "2015-05-15"),
+    (1, "2015-06-25"),
+    (2, "2015-08-07"),
+    (3, "2015-09-17"),
+    (4, "2015-10-29"),
+    (5, "2015-12-10"),
+    (6, "2016-01-21"),
+    (7, "2016-03-03"),
+    (8, "2016-04-14"),
+    (9, "2016-05-26"),
+    (10, "2016-07-07"),
+    (11, "2016-08-18"),
+    (12, "2016-09-29"),
+    (13, "2016-11-10"),
+    (14, "2016-12-22"),
+    (15, "2017-02-02"),
+    (16, "2017-03-16"),
+    (17, "2017-04-27"),
+    (18, "2017-06-08"),
+    (19, "2017-07-20"),
+    (20, "2017-08-31"),
+    (21, "2017-10-12"),
+    (22, "2017-11-22"),
+    (23, "2018-01-04"),
+    (24, "2018-02-15"),
+    (25, "2018-03-29"),
+    (26, "2018-05-10"),
+    (27, "2018-06-21"),
+    (28, "2018-08-02"),
+    (29, "2018-09-13"),
+    (30, "2018-10-25"),
+    (31, "2018-12-06"),
+    (32, "2019-01-17"),
+    (33, "2019-02-28"),
+    (34, "2019-04-11"),
+    (35, "2019-05-23"),
+    (36, "2019-07-04"),
+    (37, "2019-08-15"),
+    (38, "2019-09-26"),
+    (39, "2019-11-07"),
+    (40, "2019-12-19"),
+    (41, "2020-01-30"),
+    (42, "2020-03-12"),
+    (43, "2020-04-23"),
+    (44, "2020-06-04"),
+    (45, "2020-07-16"),
+    (46, "2020-08-27"),
+    (47, "2020-10-08"),
+    (48, "2020-11-19"),
+    (49, "2020-12-31"),
+    (50, "2021-02-11"),
+    (51, "2021-03-25"),
+    (52, "2021-05-06"),
+    (53, "2021-06-17"),
+    (54, "2021-07-29"),
+    (55, "2021-09-09"),
+    (56, "2021-10-21"),
+    (57, "2021-12-02"),
+    (58, "2022-01-13"),
+    (59, "2022-02-24"),
+    (60, "2022-04-07"),
+    (61, "2022-05-19"),
+    (62, "2022-06-30"),
+    (63, "2022-08-11"),
+    (64, "2022-09-22"),
+    (65, "2022-11-03"),
+    (66, "2022-12-15"),
+    (67, "2023-01-26"),
+    (68, "2023-03-09"),
+    (69, "2023-04-20"),
+    (70, "2023-06-01"),
+    (71, "2023-07-13"),
+    (72, "2023-08-24"),
+    (73, "2023-10-05"),
+    (74, "2023-11-16"),
+    (75, "2023-12-28"),
+    (76, "2024-02-08"),
+    (77, "2024-03-21"),
+    (78, "2024-05-02"),
+    (79, "2024-06-13"),
+    (80, "2024-07-25"),
+    (81, "2024-09-05"),
+    (82, "2024-10-17"),
+    (83, "2024-11-28"),
+    (84, "2025-01-09"),
+    (85, "2025-02-20"),
+    (86, "2025-04-03"),
+    (87, "2025-05-15"),
+    (88, "2025-06-26"),
+    (89, "2025-08-07"),
+    (90, "2025-09-18"),
+    (91, "2025-10-30"),
+    (92, "2025-12-11"),
+    (93, "2026-01-22"),
+    (94, "2026-03-05"),
+    (95, "2026-04-16"),
+    (96, "2026-05-28"),
+    (97, "2026-07-09"),
+    (98, "2026-08-20"),
+    (99, "2026-10-01"),
+];
+
+/// The number of releases in the calendar, which starts at Rust 1.0.
+pub const KNOWN_RELEASES: usize = RELEASE_DATES.len();
+
+// The histogram columns assume that no release is missing.
+const _: () = {
+    let mut i = 0;
+    while i < RELEASE_DATES.len() {
+        assert!(RELEASE_DATES[i].0 == i, "release calendar has gaps");
+        i += 1;
+    }
+};
+
+/// The release date of a minor version of Rust 1, if it has been released.
+pub fn release_date(minor: usize) -> Option<NaiveDate> {
+    let (_, date) = RELEASE_DATES
+        .iter()
+        .find(|(release, _)| *release == minor)?;
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// The number of whole days between the release of a minor version and a Unix timestamp,
+/// negative if the timestamp is before the release.
+pub fn days_since_release(minor: usize, timestamp: i64) -> Option<i64> {
+    let released = release_date(minor)?.and_hms_opt(0, 0, 0)?.timestamp();
+    Some((timestamp - released).div_euclid(SECONDS_PER_DAY))
+}
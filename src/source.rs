@@ -0,0 +1,400 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{ensure, Context};
+use log::{trace, warn};
+
+use proc_macro2::{Delimiter, TokenStream, TokenTree};
+
+use crate::analyzer::{is_cfg_test, is_cfg_test_attr, item_attrs};
+
+pub struct SourceFile {
+    /// Module path of the file relative to the crate root, which has an empty path.
+    pub module: Vec<String>,
+    pub source: String,
+    // Old code does not always parse with syn (e.g. anonymous parameters), but we keep the source.
+    pub file: Option<syn::File>,
+}
+
+/// Loads all files of a crate's module tree, starting at its root file.
+pub fn load_module_tree(root: &Path) -> anyhow::Result<Vec<SourceFile>> {
+    let mut loader = ModuleTreeLoader {
+        files: Vec::new(),
+        visited: HashSet::new(),
+    };
+
+    let dir = root.parent().context("crate root has no parent")?;
+    loader.load_file(root, dir.to_path_buf(), Vec::new())?;
+
+    Ok(loader.files)
+}
+
+/// Merges a loaded module tree into a single file with inline modules, like `cargo expand`
+/// would give us, but without expanding any macros.
+pub fn merge_module_tree(sources: &[SourceFile]) -> anyhow::Result<syn::File> {
+    let (root, modules) = sources.split_first().context("no crate root")?;
+    let mut file = root.file.clone().context("could not parse crate root")?;
+
+    for source in modules {
+        let Some(module_file) = &source.file else {
+            continue;
+        };
+
+        if let Some(module) = find_module_declaration(&mut file.items, &source.module) {
+            module.content = Some((Default::default(), module_file.items.clone()));
+            module.semi = None;
+        }
+    }
+
+    // Expanding without `--tests` removes these as well.
+    remove_cfg_test(&mut file.items);
+
+    Ok(file)
+}
+
+// Finds the first `mod name;` at the given path that has not been filled in yet.
+fn find_module_declaration<'a>(
+    items: &'a mut [syn::Item],
+    path: &[String],
+) -> Option<&'a mut syn::ItemMod> {
+    let (name, rest) = path.split_first()?;
+
+    for item in items {
+        let syn::Item::Mod(module) = item else {
+            continue;
+        };
+
+        if module.ident != name {
+            continue;
+        }
+
+        if rest.is_empty() {
+            if module.content.is_none() {
+                return Some(module);
+            }
+        } else if let Some((_, items)) = &mut module.content {
+            if let Some(module) = find_module_declaration(items, rest) {
+                return Some(module);
+            }
+        }
+    }
+
+    None
+}
+
+fn remove_cfg_test(items: &mut Vec<syn::Item>) {
+    items.retain(|item| !is_cfg_test(item_attrs(item)));
+
+    for item in items {
+        if let syn::Item::Mod(syn::ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = item
+        {
+            remove_cfg_test(items);
+        }
+    }
+}
+
+/// The cfg options that are set when compiling for a target, like `unix` or `target_os="linux"`.
+pub struct TargetCfg {
+    options: HashSet<(String, Option<String>)>,
+}
+
+impl TargetCfg {
+    pub fn from_rustc(toolchain: Option<&str>, target: &str) -> anyhow::Result<TargetCfg> {
+        let mut rustc = Command::new("rustc");
+        if let Some(toolchain) = toolchain {
+            rustc.arg(format!("+{toolchain}"));
+        }
+        let rustc = rustc
+            .args(["--print", "cfg", "--target", target])
+            .output()
+            .context("failed to execute rustc")?;
+
+        ensure!(
+            rustc.status.success(),
+            "could not get cfg of {target}: {}",
+            String::from_utf8_lossy(&rustc.stderr).trim()
+        );
+
+        let options = String::from_utf8(rustc.stdout)?
+            .lines()
+            .map(|line| match line.split_once('=') {
+                Some((key, value)) => (key.to_string(), Some(value.trim_matches('"').to_string())),
+                None => (line.to_string(), None),
+            })
+            .collect();
+
+        Ok(TargetCfg { options })
+    }
+
+    // Options we don't know about, like `feature = "..."` or `doc`, are not set.
+    fn evaluate(&self, predicate: &syn::Meta) -> bool {
+        match predicate {
+            syn::Meta::Path(path) => path
+                .get_ident()
+                .is_some_and(|ident| self.options.contains(&(ident.to_string(), None))),
+            syn::Meta::NameValue(meta) => {
+                let (
+                    Some(key),
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(value),
+                        ..
+                    }),
+                ) = (meta.path.get_ident(), &meta.value)
+                else {
+                    return false;
+                };
+
+                self.options
+                    .contains(&(key.to_string(), Some(value.value())))
+            }
+            syn::Meta::List(list) => {
+                let Ok(predicates) = list.parse_args_with(
+                    syn::punctuated::Punctuated::<syn::Meta, syn::Token![,]>::parse_terminated,
+                ) else {
+                    return false;
+                };
+
+                if list.path.is_ident("all") {
+                    predicates.iter().all(|predicate| self.evaluate(predicate))
+                } else if list.path.is_ident("any") {
+                    predicates.iter().any(|predicate| self.evaluate(predicate))
+                } else if list.path.is_ident("not") {
+                    predicates.len() == 1 && !self.evaluate(&predicates[0])
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn is_enabled(&self, attrs: &[syn::Attribute]) -> bool {
+        attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .all(|attr| {
+                attr.parse_args::<syn::Meta>()
+                    .is_ok_and(|predicate| self.evaluate(&predicate))
+            })
+    }
+}
+
+/// Removes the items (and inherent impl items) that are configured out on the given target.
+pub fn remove_disabled_items(items: &mut Vec<syn::Item>, cfg: &TargetCfg) {
+    items.retain(|item| cfg.is_enabled(item_attrs(item)));
+
+    for item in items {
+        match item {
+            syn::Item::Mod(syn::ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => remove_disabled_items(items, cfg),
+            syn::Item::Impl(item) => item.items.retain(|item| {
+                let attrs = match item {
+                    syn::ImplItem::Const(item) => &item.attrs,
+                    syn::ImplItem::Fn(item) => &item.attrs,
+                    syn::ImplItem::Type(item) => &item.attrs,
+                    _ => return true,
+                };
+
+                cfg.is_enabled(attrs)
+            }),
+            _ => {}
+        }
+    }
+}
+
+// The value of a `#[path = "..."]` attribute.
+fn path_attr(attrs: &[syn::Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| {
+        let syn::Meta::NameValue(meta) = &attr.meta else {
+            return None;
+        };
+
+        let syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(path),
+            ..
+        }) = &meta.value
+        else {
+            return None;
+        };
+
+        meta.path.is_ident("path").then(|| path.value())
+    })
+}
+
+// Same as `path_attr`, but for the attribute tokens right before a `mod`.
+fn path_attr_from_tokens(tokens: &[TokenTree]) -> Option<String> {
+    let mut end = tokens.len();
+    while end >= 2 {
+        let (TokenTree::Punct(punct), TokenTree::Group(group)) =
+            (&tokens[end - 2], &tokens[end - 1])
+        else {
+            return None;
+        };
+
+        if punct.as_char() != '#' || group.delimiter() != Delimiter::Bracket {
+            return None;
+        }
+
+        if let Ok(meta) = syn::parse2::<syn::Meta>(group.stream()) {
+            if let Some(path) = path_attr(&[syn::parse_quote!(#[#meta])]) {
+                return Some(path);
+            }
+        }
+
+        end -= 2;
+    }
+
+    None
+}
+
+struct ModuleTreeLoader {
+    files: Vec<SourceFile>,
+    visited: HashSet<PathBuf>,
+}
+
+impl ModuleTreeLoader {
+    // Modules of this file are looked up in `dir`, which is different from the directory
+    // of the file itself for `foo.rs` files (`foo/bar.rs`).
+    fn load_file(&mut self, path: &Path, dir: PathBuf, module: Vec<String>) -> anyhow::Result<()> {
+        if !self.visited.insert(path.to_path_buf()) {
+            return Ok(());
+        }
+
+        trace!("loading {}...", path.display());
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let file = match syn::parse_file(&source) {
+            Ok(file) => Some(file),
+            Err(err) => {
+                warn!("could not parse {}: {err}", path.display());
+                None
+            }
+        };
+
+        // `#[path]` attributes outside of inline modules are relative to the file itself.
+        let path_dir = path.parent().context("source file has no parent")?;
+
+        // Parents come before their modules, so the crate root is always first.
+        let index = self.files.len();
+        if let Some(file) = &file {
+            self.load_modules(&file.items, &dir, path_dir, &module);
+        } else if let Ok(tokens) = source.parse::<TokenStream>() {
+            self.load_modules_from_tokens(tokens, &dir, path_dir, &module);
+        }
+
+        self.files.insert(
+            index,
+            SourceFile {
+                module,
+                source,
+                file,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn load_modules(
+        &mut self,
+        items: &[syn::Item],
+        dir: &Path,
+        path_dir: &Path,
+        module: &[String],
+    ) {
+        for item in items {
+            let syn::Item::Mod(item) = item else {
+                continue;
+            };
+
+            if is_cfg_test(&item.attrs) {
+                continue;
+            }
+
+            let name = item.ident.to_string();
+            let mut module = module.to_vec();
+            module.push(name.clone());
+
+            if let Some((_, items)) = &item.content {
+                let dir = dir.join(&name);
+                self.load_modules(items, &dir, &dir, &module);
+            } else {
+                let path = path_attr(&item.attrs).map(|path| path_dir.join(path));
+                self.load_module_file(path, dir, module);
+            }
+        }
+    }
+
+    // Files that syn can't parse can still declare modules, so we look for `mod name;` in the
+    // tokens.
+    fn load_modules_from_tokens(
+        &mut self,
+        tokens: TokenStream,
+        dir: &Path,
+        path_dir: &Path,
+        module: &[String],
+    ) {
+        let tokens = tokens.into_iter().collect::<Vec<_>>();
+
+        for i in 0..tokens.len() {
+            let (Some(TokenTree::Ident(keyword)), Some(TokenTree::Ident(name))) =
+                (tokens.get(i), tokens.get(i + 1))
+            else {
+                continue;
+            };
+
+            if keyword != "mod" || (i >= 2 && is_cfg_test_attr(&tokens[i - 2..])) {
+                continue;
+            }
+
+            let name = name.to_string();
+            let mut module = module.to_vec();
+            module.push(name.clone());
+
+            match tokens.get(i + 2) {
+                Some(TokenTree::Punct(punct)) if punct.as_char() == ';' => {
+                    let path = path_attr_from_tokens(&tokens[..i]).map(|path| path_dir.join(path));
+                    self.load_module_file(path, dir, module);
+                }
+                Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                    let dir = dir.join(&name);
+                    self.load_modules_from_tokens(group.stream(), &dir, &dir, &module);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn load_module_file(&mut self, path: Option<PathBuf>, dir: &Path, module: Vec<String>) {
+        let name = module.last().expect("module should have a name");
+
+        let (path, module_dir) = match path {
+            // Files from `#[path]` are treated like `mod.rs` files.
+            Some(path) => {
+                let module_dir = path.parent().unwrap_or(dir).to_path_buf();
+                (path, module_dir)
+            }
+            None => {
+                let module_dir = dir.join(name);
+                let candidates = [dir.join(format!("{name}.rs")), module_dir.join("mod.rs")];
+                let Some(path) = candidates.into_iter().find(|path| path.is_file()) else {
+                    warn!("could not find module {name} in {}", dir.display());
+                    return;
+                };
+
+                (path, module_dir)
+            }
+        };
+
+        if let Err(err) = self.load_file(&path, module_dir, module) {
+            warn!("{err:#}");
+        }
+    }
+}
@@ -1,27 +1,138 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process::Command,
 };
 
-use anyhow::Context;
-use log::{debug, trace};
-use serde::{Deserialize, Serialize};
+use anyhow::{ensure, Context};
+use joinery::JoinableIterator;
+use log::{debug, trace, warn};
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use serde_json::Value;
 use syn::UseTree;
 
+use crate::source::{load_module_tree, merge_module_tree, remove_disabled_items, TargetCfg};
+
 #[derive(deluxe::ExtractAttributes)]
 #[deluxe(attributes(stable))]
 struct Stable {
-    #[allow(dead_code)]
     pub feature: String,
     pub since: String,
 }
 
+#[derive(deluxe::ExtractAttributes)]
+#[deluxe(attributes(unstable), allow_unknown_fields)]
+struct Unstable {
+    pub feature: String,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
-struct VersionedItem {
+pub struct Deprecation {
+    pub since: Option<String>,
+    pub note: Option<String>,
+    pub suggestion: Option<String>,
+}
+
+impl Deprecation {
+    // Both `#[deprecated]` and the older `#[rustc_deprecated]`, which used `reason` instead of
+    // `note`.
+    fn from_attributes(attrs: &[syn::Attribute]) -> Option<Deprecation> {
+        let attr = attrs.iter().find(|attr| {
+            attr.path().is_ident("deprecated") || attr.path().is_ident("rustc_deprecated")
+        })?;
+
+        let mut deprecation = Deprecation {
+            since: None,
+            note: None,
+            suggestion: None,
+        };
+
+        match &attr.meta {
+            syn::Meta::Path(_) => {}
+            syn::Meta::NameValue(meta) => {
+                if let syn::Expr::Lit(syn::ExprLit {
+                    lit: syn::Lit::Str(note),
+                    ..
+                }) = &meta.value
+                {
+                    deprecation.note = Some(note.value());
+                }
+            }
+            syn::Meta::List(list) => {
+                let _ = list.parse_nested_meta(|meta| {
+                    let value = meta.value()?.parse::<syn::LitStr>()?.value();
+                    if meta.path.is_ident("since") {
+                        deprecation.since = Some(value);
+                    } else if meta.path.is_ident("note") || meta.path.is_ident("reason") {
+                        deprecation.note = Some(value);
+                    } else if meta.path.is_ident("suggestion") {
+                        deprecation.suggestion = Some(value);
+                    }
+
+                    Ok(())
+                });
+            }
+        }
+
+        Some(deprecation)
+    }
+
+    // Deprecations with `since = "TBD"` only take effect in a future release.
+    pub fn is_in_effect(&self) -> bool {
+        self.since.as_deref() != Some("TBD")
+    }
+
+    /// The suggested replacement, falling back to the first `code` span in the note.
+    pub fn replacement(&self) -> Option<&str> {
+        self.suggestion
+            .as_deref()
+            .or_else(|| self.note.as_deref()?.split('`').nth(1))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Stability {
+    /// Stable since the given Rust version.
+    Stable(String),
+    /// Only available on nightly behind the given feature gate.
+    Unstable(String),
+    /// Not annotated, but takes the stability of its parent (enum variants and trait items).
+    Inherited,
+    /// Not annotated at all, e.g. private modules and other intermediate path nodes.
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VersionedItem {
     #[serde(skip)]
     name: String,
-    version: String,
+    stability: Stability,
+    /// Feature name of the stability attribute.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    feature: Option<String>,
     public: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deprecation: Option<Deprecation>,
+    /// Version since which the item can be used in const contexts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    const_since: Option<String>,
+    /// First snapshot in a merged database that no longer has the item.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    removed_in: Option<String>,
+    /// First snapshot in a merged database in which the item is deprecated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deprecated_in: Option<String>,
+    /// Path of the item that replaces a deprecated or removed one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    successor: Option<String>,
+    /// Targets the item is available on, if the database was built for specific targets.
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    targets: BTreeSet<String>,
     children: HashMap<String, VersionedItem>,
 }
 
@@ -29,14 +140,92 @@ impl VersionedItem {
     pub fn new(name: String) -> VersionedItem {
         Self {
             name,
-            version: "1.0.0".to_string(),
+            stability: Stability::Unknown,
+            feature: None,
             public: true,
+            deprecation: None,
+            const_since: None,
+            removed_in: None,
+            deprecated_in: None,
+            successor: None,
+            targets: BTreeSet::new(),
             children: HashMap::new(),
         }
     }
 
+    pub fn deprecation(&self) -> Option<&Deprecation> {
+        self.deprecation.as_ref()
+    }
+
+    pub fn const_since(&self) -> Option<&str> {
+        self.const_since.as_deref()
+    }
+
+    pub fn successor(&self) -> Option<&str> {
+        self.successor.as_deref()
+    }
+
+    // Takes the attributes of the snapshot's item, as newer snapshots come later.
+    fn merge(&mut self, snapshot: &VersionedItem, version: &str) {
+        self.stability = snapshot.stability.clone();
+        self.feature = snapshot.feature.clone();
+        self.public = snapshot.public;
+        self.const_since = snapshot.const_since.clone();
+        self.removed_in = None;
+
+        match &snapshot.deprecation {
+            Some(deprecation) if deprecation.is_in_effect() => {
+                self.deprecated_in
+                    .get_or_insert_with(|| version.to_string());
+            }
+            _ => self.deprecated_in = None,
+        }
+        self.deprecation = snapshot.deprecation.clone();
+
+        for (name, child) in self.children.iter_mut() {
+            if !snapshot.children.contains_key(name) {
+                child.mark_removed(version);
+            }
+        }
+
+        for (name, child) in snapshot.children.iter() {
+            self.children
+                .entry(name.clone())
+                .or_insert_with(|| VersionedItem::new(format!("{}::{}", self.name, name)))
+                .merge(child, version);
+        }
+    }
+
+    // Items keep the attributes of the first target they were found on.
+    fn merge_target(&mut self, snapshot: &VersionedItem, target: &str) {
+        if self.targets.is_empty() {
+            self.stability = snapshot.stability.clone();
+            self.feature = snapshot.feature.clone();
+            self.public = snapshot.public;
+            self.deprecation = snapshot.deprecation.clone();
+            self.const_since = snapshot.const_since.clone();
+        }
+
+        self.targets.insert(target.to_string());
+
+        for (name, child) in snapshot.children.iter() {
+            self.children
+                .entry(name.clone())
+                .or_insert_with(|| VersionedItem::new(format!("{}::{}", self.name, name)))
+                .merge_target(child, target);
+        }
+    }
+
+    fn mark_removed(&mut self, version: &str) {
+        self.removed_in.get_or_insert_with(|| version.to_string());
+
+        for child in self.children.values_mut() {
+            child.mark_removed(version);
+        }
+    }
+
     // pub fn dump_all_to_stdout(&self, prefix: &str) {
-    //     println!("{prefix} = {}", self.version);
+    //     println!("{prefix} = {:?}", self.stability);
     //     for (name, item) in self.children.iter() {
     //         item.dump_all_to_stdout(&format!("{prefix}::{name}"));
     //     }
@@ -53,17 +242,38 @@ impl VersionedItem {
 //     }
 // }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// A row of the exported database.
+#[derive(Serialize, Debug)]
+pub struct ExportedItem {
+    pub path: String,
+    pub canonical: String,
+    pub since: Option<String>,
+    pub feature: Option<String>,
+    pub unstable: bool,
+    pub public: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 enum LocalAlias {
     Named(String),
     GlobChildren,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 struct Alias {
     root: Vec<String>,
     relative_path: Vec<String>,
     local: LocalAlias,
+    /// Only `pub use` makes names visible outside of std.
+    public: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Export {
+    /// Path of the node with the stability, which is the re-export's own if it has one.
+    item: String,
+    /// The single public path of the item the path refers to.
+    canonical: String,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -71,6 +281,19 @@ pub struct VersionConstructor {
     root: VersionedItem,
     aliases: Vec<Alias>,
 
+    // Method calls can't be resolved without type information, so we keep track
+    // of which method names are deprecated wherever std defines them.
+    methods: HashMap<String, Option<Deprecation>>,
+
+    /// Renamed `extern crate`s by crate, like `alloc_crate` in std.
+    #[serde(default)]
+    extern_crates: HashMap<String, HashMap<String, String>>,
+
+    /// Every public path that can be written to reach an item, including re-exports and
+    /// the prelude.
+    #[serde(default)]
+    exports: HashMap<String, Export>,
+
     #[serde(skip)]
     path_stack: VecDeque<String>,
 }
@@ -80,10 +303,97 @@ impl VersionConstructor {
         VersionConstructor {
             root: VersionedItem::new("".to_string()),
             aliases: Vec::new(),
+            methods: HashMap::new(),
+            extern_crates: HashMap::new(),
+            exports: HashMap::new(),
             path_stack: VecDeque::new(),
         }
     }
 
+    // The prelude is in scope everywhere. Once all items and aliases are known, we can
+    // build the export index that all lookups go through.
+    fn finish(&mut self) {
+        let prelude = Alias {
+            root: vec![],
+            relative_path: vec!["std".to_string(), "prelude".to_string(), "v1".to_string()],
+            local: LocalAlias::GlobChildren,
+            public: true,
+        };
+
+        if !self.aliases.contains(&prelude) {
+            self.aliases.push(prelude);
+        }
+
+        self.link_macro_reexports();
+        self.exports = ExportIndexBuilder::new(self).build();
+        debug!("export index has {} paths", self.exports.len());
+    }
+
+    // `use` imports a name in every namespace, so re-exports of macros like `pub use core::matches`
+    // are written without the `!`. We link them to the macro, and drop the node with the stability
+    // of the `use` unless something else of that name is re-exported as well. Re-exports of
+    // re-exports only resolve to the macro once the first one is linked.
+    fn link_macro_reexports(&mut self) {
+        loop {
+            let reexports = ExportIndexBuilder::new(self).macro_reexports();
+            if reexports.is_empty() {
+                break;
+            }
+
+            for (alias, macro_only) in reexports {
+                if macro_only {
+                    let LocalAlias::Named(name) = &alias.local else {
+                        continue;
+                    };
+
+                    let local = name.trim_end_matches('!');
+                    self.remove_leaf(&[alias.root.as_slice(), &[local.to_string()]].concat());
+                }
+
+                self.aliases.push(alias);
+            }
+        }
+    }
+
+    fn remove_leaf(&mut self, path: &[String]) {
+        let Some((name, parent)) = path.split_last() else {
+            return;
+        };
+
+        let mut current = &mut self.root;
+        for segment in parent {
+            let Some(child) = current.children.get_mut(segment) else {
+                return;
+            };
+            current = child;
+        }
+
+        if current
+            .children
+            .get(name)
+            .is_some_and(|child| child.children.is_empty())
+        {
+            current.children.remove(name);
+        }
+    }
+
+    /// Writes the database to `path`, as JSON if it has a `.json` extension and as
+    /// (much faster to load) CBOR otherwise.
+    pub fn save(&self, path: &Path, metadata: CacheMetadata) -> anyhow::Result<()> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let cache = Cache {
+            metadata,
+            database: self,
+        };
+
+        if is_json(path) {
+            serde_json::to_writer(BufWriter::new(file), &cache).context("failed to write cache")
+        } else {
+            ciborium::into_writer(&cache, BufWriter::new(file)).context("failed to write cache")
+        }
+    }
+
     pub fn process_file(&mut self, name: String, file: syn::File) {
         self.push_path(name);
         for item in file.items {
@@ -96,10 +406,11 @@ impl VersionConstructor {
         match item {
             syn::Item::Const(item) => self.process_item_const(item),
             syn::Item::Enum(item) => self.process_item_enum(item),
+            syn::Item::ExternCrate(item) => self.process_item_extern_crate(item),
             syn::Item::Fn(item) => self.process_item_fn(item),
             // syn::Item::ForeignMod(item) => todo!(),
             syn::Item::Impl(item) => self.process_item_impl(item),
-            // syn::Item::Macro(item) => todo!(),
+            syn::Item::Macro(item) => self.process_item_macro(item),
             syn::Item::Mod(item) => self.process_item_mod(item),
             syn::Item::Static(item) => self.process_item_static(item),
             syn::Item::Struct(item) => self.process_item_struct(item),
@@ -108,11 +419,48 @@ impl VersionConstructor {
             syn::Item::Type(item) => self.process_item_type(item),
             syn::Item::Union(item) => self.process_item_union(item),
             syn::Item::Use(item) => self.process_item_use(item),
-            // syn::Item::Verbatim(item) => todo!(),
+            syn::Item::Verbatim(item) => self.process_item_verbatim(item),
             _ => {}
         }
     }
 
+    // Macros live in their own namespace, so we store them as `name!` to avoid
+    // clashing with modules like `vec`.
+    fn process_item_macro(&mut self, item: syn::ItemMacro) {
+        let Some(ident) = item.ident else {
+            return;
+        };
+
+        // `#[macro_export]` places a macro at the crate root, wherever it is defined.
+        if item
+            .attrs
+            .iter()
+            .any(|attr| attr.path().is_ident("macro_export"))
+        {
+            let path_stack = std::mem::take(&mut self.path_stack);
+            self.push_path(path_stack[0].clone());
+            self.push_version_from_attributes(format!("{ident}!"), item.attrs, true);
+            self.path_stack = path_stack;
+        }
+    }
+
+    // Declarative macros 2.0 (`pub macro name { .. }`) are not supported by syn.
+    fn process_item_verbatim(&mut self, tokens: proc_macro2::TokenStream) {
+        let parser = |input: syn::parse::ParseStream| {
+            let attrs = input.call(syn::Attribute::parse_outer)?;
+            let vis = input.parse::<syn::Visibility>()?;
+            input.parse::<syn::Token![macro]>()?;
+            let ident = input.parse::<syn::Ident>()?;
+            input.parse::<proc_macro2::TokenStream>()?;
+
+            Ok((attrs, vis, ident))
+        };
+
+        if let Ok((attrs, vis, ident)) = syn::parse::Parser::parse2(parser, tokens) {
+            self.push_version_from_attributes(format!("{ident}!"), attrs, is_public(vis));
+        }
+    }
+
     fn process_item_const(&mut self, item: syn::ItemConst) {
         self.push_version_from_attributes(item.ident.to_string(), item.attrs, is_public(item.vis));
     }
@@ -122,11 +470,39 @@ impl VersionConstructor {
 
         self.push_path(item.ident.to_string());
         for variant in item.variants {
-            self.push_version_from_attributes(variant.ident.to_string(), variant.attrs, true);
+            self.push_inherited_version_from_attributes(
+                variant.ident.to_string(),
+                variant.attrs,
+                true,
+            );
         }
         self.pop_path();
     }
 
+    fn process_item_extern_crate(&mut self, item: syn::ItemExternCrate) {
+        if let Some((_, rename)) = item.rename {
+            self.record_extern_crate(item.ident.to_string(), rename.to_string());
+        }
+    }
+
+    // Renamed crates can be used anywhere in the crate, so we keep them per crate instead of
+    // as an alias in the module.
+    fn record_extern_crate(&mut self, name: String, rename: String) {
+        let Some(krate) = self.path_stack.front().cloned() else {
+            return;
+        };
+
+        if rename == "_" {
+            return;
+        }
+
+        let name = if name == "self" { krate.clone() } else { name };
+        self.extern_crates
+            .entry(krate)
+            .or_default()
+            .insert(rename, name);
+    }
+
     fn process_item_fn(&mut self, item: syn::ItemFn) {
         self.push_version_from_attributes(
             item.sig.ident.to_string(),
@@ -163,11 +539,12 @@ impl VersionConstructor {
     }
 
     fn process_impl_fn(&mut self, item: syn::ImplItemFn) {
-        self.push_version_from_attributes(
-            item.sig.ident.to_string(),
-            item.attrs,
-            is_public(item.vis),
-        );
+        let name = item.sig.ident.to_string();
+        let deprecation = Deprecation::from_attributes(&item.attrs);
+
+        if self.push_version_from_attributes(name.clone(), item.attrs, is_public(item.vis)) {
+            self.record_method(name, deprecation);
+        }
     }
 
     fn process_impl_type(&mut self, item: syn::ImplItemType) {
@@ -179,7 +556,7 @@ impl VersionConstructor {
             return;
         };
 
-        self.push_version_from_attributes(item.ident.to_string(), item.attrs, is_public(item.vis));
+        self.push_module_from_attributes(item.ident.to_string(), item.attrs, is_public(item.vis));
 
         self.push_path(item.ident.to_string());
         for item in items {
@@ -214,15 +591,34 @@ impl VersionConstructor {
     }
 
     fn process_trait_const(&mut self, item: syn::TraitItemConst) {
-        self.push_version_from_attributes(item.ident.to_string(), item.attrs, true);
+        self.push_inherited_version_from_attributes(item.ident.to_string(), item.attrs, true);
     }
 
     fn process_trait_fn(&mut self, item: syn::TraitItemFn) {
-        self.push_version_from_attributes(item.sig.ident.to_string(), item.attrs, true);
+        let name = item.sig.ident.to_string();
+        let deprecation = Deprecation::from_attributes(&item.attrs);
+
+        self.push_inherited_version_from_attributes(name.clone(), item.attrs, true);
+        self.record_method(name, deprecation);
+    }
+
+    // Methods are tracked by name only, so within one release a name is only deprecated if every
+    // method with it is.
+    fn record_method(&mut self, name: String, deprecation: Option<Deprecation>) {
+        match self.methods.entry(name) {
+            Entry::Vacant(entry) => {
+                entry.insert(deprecation);
+            }
+            Entry::Occupied(mut entry) => {
+                if deprecation.is_none() {
+                    entry.insert(None);
+                }
+            }
+        }
     }
 
     fn process_trait_type(&mut self, item: syn::TraitItemType) {
-        self.push_version_from_attributes(item.ident.to_string(), item.attrs, true);
+        self.push_inherited_version_from_attributes(item.ident.to_string(), item.attrs, true);
     }
 
     fn process_item_trait_alias(&mut self, item: syn::ItemTraitAlias) {
@@ -238,7 +634,8 @@ impl VersionConstructor {
     }
 
     fn process_item_use(&mut self, item: syn::ItemUse) {
-        self.process_use_tree(Vec::new(), item.tree, item.attrs, false);
+        let public = is_public(item.vis);
+        self.process_use_tree(Vec::new(), item.tree, item.attrs, public);
     }
 
     fn process_use_tree(
@@ -263,12 +660,14 @@ impl VersionConstructor {
                         root: self.path_stack.clone().into(),
                         relative_path,
                         local: LocalAlias::Named(name.ident.to_string()),
+                        public,
                     });
                 } else {
                     self.aliases.push(Alias {
                         root: self.path_stack.clone().into(),
                         local: LocalAlias::Named(relative_path.last().unwrap().clone()),
                         relative_path,
+                        public,
                     });
                 }
 
@@ -283,6 +682,7 @@ impl VersionConstructor {
                     root: self.path_stack.clone().into(),
                     relative_path,
                     local: LocalAlias::Named(rename.rename.to_string()),
+                    public,
                 });
 
                 self.push_version_from_attributes(rename.ident.to_string(), attrs, public);
@@ -291,6 +691,7 @@ impl VersionConstructor {
                 root: self.path_stack.clone().into(),
                 relative_path,
                 local: LocalAlias::GlobChildren,
+                public,
             }),
             UseTree::Group(group) => {
                 for item in group.items {
@@ -300,6 +701,297 @@ impl VersionConstructor {
         }
     }
 
+    /// Merges a snapshot of a later Rust release into this database, keeping items that
+    /// have since been removed.
+    pub fn merge_snapshot(&mut self, version: &str, mut snapshot: VersionConstructor) {
+        self.root.merge(&snapshot.root, version);
+        // Snapshots are merged from old to new, so the latest one decides whether a method is
+        // deprecated.
+        self.methods.extend(std::mem::take(&mut snapshot.methods));
+        self.merge_aliases(snapshot);
+    }
+
+    /// Merges a database built for another target into this one, tagging every item
+    /// with the targets it was found on.
+    pub fn merge_target(&mut self, target: &str, mut snapshot: VersionConstructor) {
+        self.root.merge_target(&snapshot.root, target);
+        for (name, deprecation) in std::mem::take(&mut snapshot.methods) {
+            self.record_method(name, deprecation);
+        }
+        self.merge_aliases(snapshot);
+    }
+
+    fn merge_aliases(&mut self, snapshot: VersionConstructor) {
+        for alias in snapshot.aliases {
+            if !self.aliases.contains(&alias) {
+                self.aliases.push(alias);
+            }
+        }
+
+        for (krate, renames) in snapshot.extern_crates {
+            self.extern_crates.entry(krate).or_default().extend(renames);
+        }
+    }
+
+    /// Resolves the replacements that deprecation notes point to, once all snapshots are merged.
+    pub fn resolve_successors(&mut self) {
+        let mut successors = Vec::new();
+        self.find_successors(&self.root, &mut Vec::new(), &mut successors);
+
+        for (path, successor) in successors {
+            let mut current = &mut self.root;
+            for segment in path.iter() {
+                current = current
+                    .children
+                    .get_mut(segment)
+                    .expect("path was just visited");
+            }
+
+            current.successor = Some(successor);
+        }
+    }
+
+    fn find_successors(
+        &self,
+        item: &VersionedItem,
+        path: &mut Vec<String>,
+        successors: &mut Vec<(Vec<String>, String)>,
+    ) {
+        if item.removed_in.is_some() || item.deprecated_in.is_some() {
+            if let Some(successor) = item
+                .deprecation
+                .as_ref()
+                .and_then(|deprecation| self.find_successor(path, deprecation.replacement()?))
+            {
+                successors.push((path.clone(), successor));
+            }
+        }
+
+        for (name, child) in item.children.iter() {
+            path.push(name.clone());
+            self.find_successors(child, path, successors);
+            path.pop();
+        }
+    }
+
+    // Replacements are written like `std::mem::MaybeUninit`, `i32::MAX` or `checked_add()`,
+    // so we try them as absolute paths, next to the item and at the crate root.
+    fn find_successor(&self, path: &[String], replacement: &str) -> Option<String> {
+        let replacement = replacement
+            .trim_end_matches("()")
+            .split("::")
+            .map(|segment| segment.split('<').next().unwrap_or(segment).to_string())
+            .collect::<Vec<_>>();
+
+        let parent = &path[..path.len().saturating_sub(1)];
+        let candidates = [&[][..], parent, &path[..1.min(path.len())]].map(|prefix| {
+            let mut candidate = prefix.to_vec();
+            candidate.extend(replacement.iter().cloned());
+            candidate
+        });
+
+        candidates
+            .into_iter()
+            .find(|candidate| {
+                candidate != path
+                    && self
+                        .get_item(candidate)
+                        .is_some_and(|item| item.removed_in.is_none())
+            })
+            .map(|candidate| candidate.join("::"))
+    }
+
+    /// Adds a crate from rustdoc's JSON output, which records re-exports by the canonical
+    /// path of their target, so aliases don't have to be guessed from `use` trees.
+    pub fn process_rustdoc_json(&mut self, name: String, krate: &Value) -> anyhow::Result<()> {
+        let root = rustdoc_id(&krate["root"]).context("rustdoc JSON has no root module")?;
+        let items = krate["index"][root.as_str()]["inner"]["module"]["items"]
+            .as_array()
+            .context("rustdoc JSON root is not a module")?;
+
+        self.push_path(name);
+        for id in items {
+            self.process_rustdoc_item(krate, id);
+        }
+        self.pop_path();
+
+        Ok(())
+    }
+
+    fn process_rustdoc_item(&mut self, krate: &Value, id: &Value) {
+        // Items from other crates are only in `paths`, and are reached through `use` items.
+        let Some(item) = rustdoc_id(id).and_then(|id| krate["index"].get(id)) else {
+            return;
+        };
+
+        let Some((kind, inner)) = item["inner"]
+            .as_object()
+            .and_then(|inner| inner.iter().next())
+        else {
+            return;
+        };
+
+        let attrs = rustdoc_attrs(item);
+        let public = item["visibility"] == "public";
+
+        // Older format versions call these `import`.
+        if kind == "use" || kind == "import" {
+            self.process_rustdoc_use(krate, inner, attrs, public);
+            return;
+        }
+
+        if kind == "extern_crate" {
+            if let (Some(name), Some(rename)) = (inner["name"].as_str(), inner["rename"].as_str()) {
+                self.record_extern_crate(name.to_string(), rename.to_string());
+            }
+            return;
+        }
+
+        let Some(name) = item["name"].as_str().map(str::to_string) else {
+            return;
+        };
+
+        match kind.as_str() {
+            "module" => {
+                self.push_module_from_attributes(name.clone(), attrs, public);
+
+                self.push_path(name);
+                for id in inner["items"].as_array().into_iter().flatten() {
+                    self.process_rustdoc_item(krate, id);
+                }
+                self.pop_path();
+            }
+            "struct" | "union" | "primitive" => {
+                self.push_version_from_attributes(name.clone(), attrs, public);
+                self.process_rustdoc_impls(krate, name, inner);
+            }
+            "enum" => {
+                self.push_version_from_attributes(name.clone(), attrs, public);
+
+                self.push_path(name.clone());
+                for id in inner["variants"].as_array().into_iter().flatten() {
+                    let variant = rustdoc_id(id).map_or(&Value::Null, |id| &krate["index"][id]);
+                    if let Some(variant_name) = variant["name"].as_str() {
+                        self.push_inherited_version_from_attributes(
+                            variant_name.to_string(),
+                            rustdoc_attrs(variant),
+                            true,
+                        );
+                    }
+                }
+                self.pop_path();
+
+                self.process_rustdoc_impls(krate, name, inner);
+            }
+            "trait" => {
+                self.push_version_from_attributes(name.clone(), attrs, public);
+
+                self.push_path(name);
+                for id in inner["items"].as_array().into_iter().flatten() {
+                    let item = rustdoc_id(id).map_or(&Value::Null, |id| &krate["index"][id]);
+                    let Some(item_name) = item["name"].as_str().map(str::to_string) else {
+                        continue;
+                    };
+
+                    let attrs = rustdoc_attrs(item);
+                    if item["inner"].get("function").is_some() {
+                        let deprecation = Deprecation::from_attributes(&attrs);
+                        self.push_inherited_version_from_attributes(item_name.clone(), attrs, true);
+                        self.record_method(item_name, deprecation);
+                    } else {
+                        self.push_inherited_version_from_attributes(item_name, attrs, true);
+                    }
+                }
+                self.pop_path();
+            }
+            "macro" | "proc_macro" => {
+                self.push_version_from_attributes(format!("{name}!"), attrs, public);
+            }
+            "function" | "constant" | "static" | "type_alias" | "typedef" | "trait_alias" => {
+                self.push_version_from_attributes(name, attrs, public);
+            }
+            _ => {}
+        }
+    }
+
+    fn process_rustdoc_use(
+        &mut self,
+        krate: &Value,
+        inner: &Value,
+        attrs: Vec<syn::Attribute>,
+        public: bool,
+    ) {
+        let Some(source) = inner["source"].as_str() else {
+            return;
+        };
+
+        // The canonical path of the target, if rustdoc could resolve it.
+        let target = rustdoc_id(&inner["id"])
+            .and_then(|id| krate["paths"][id]["path"].as_array())
+            .and_then(|path| {
+                path.iter()
+                    .map(|segment| segment.as_str().map(str::to_string))
+                    .collect::<Option<Vec<_>>>()
+            });
+
+        let relative_path =
+            target.unwrap_or_else(|| source.split("::").map(str::to_string).collect());
+
+        if inner["is_glob"].as_bool().unwrap_or_default() {
+            self.aliases.push(Alias {
+                root: self.path_stack.clone().into(),
+                relative_path,
+                local: LocalAlias::GlobChildren,
+                public,
+            });
+            return;
+        }
+
+        let Some(name) = inner["name"].as_str().map(str::to_string) else {
+            return;
+        };
+
+        self.aliases.push(Alias {
+            root: self.path_stack.clone().into(),
+            relative_path,
+            local: LocalAlias::Named(name.clone()),
+            public,
+        });
+
+        self.push_version_from_attributes(name, attrs, public);
+    }
+
+    // Inherent impls only, like `process_item_impl`.
+    fn process_rustdoc_impls(&mut self, krate: &Value, name: String, inner: &Value) {
+        self.push_path(name);
+        for id in inner["impls"].as_array().into_iter().flatten() {
+            let item = rustdoc_id(id).map_or(&Value::Null, |id| &krate["index"][id]);
+            let inner = &item["inner"]["impl"];
+            if !inner["trait_"].is_null() {
+                continue;
+            }
+
+            for id in inner["items"].as_array().into_iter().flatten() {
+                let item = rustdoc_id(id).map_or(&Value::Null, |id| &krate["index"][id]);
+                let Some(item_name) = item["name"].as_str().map(str::to_string) else {
+                    continue;
+                };
+
+                let attrs = rustdoc_attrs(item);
+                let public = item["visibility"] == "public";
+                if item["inner"].get("function").is_some() {
+                    let deprecation = Deprecation::from_attributes(&attrs);
+                    if self.push_version_from_attributes(item_name.clone(), attrs, public) {
+                        self.record_method(item_name, deprecation);
+                    }
+                } else {
+                    self.push_version_from_attributes(item_name, attrs, public);
+                }
+            }
+        }
+        self.pop_path();
+    }
+
     fn push_path(&mut self, path: String) {
         self.path_stack.push_back(path);
     }
@@ -336,13 +1028,49 @@ impl VersionConstructor {
         name: String,
         mut attrs: Vec<syn::Attribute>,
         public: bool,
+    ) -> bool {
+        let deprecation = Deprecation::from_attributes(&attrs);
+        let const_since = const_stable_since(&attrs);
+        let Some((stability, feature)) = extract_stability(&mut attrs) else {
+            return false;
+        };
+
+        self.push_path(name);
+        self.push_version(stability, Some(feature), public, deprecation, const_since);
+        self.pop_path();
+
+        true
+    }
+
+    // Unannotated modules still decide whether the items in them can be named.
+    fn push_module_from_attributes(
+        &mut self,
+        name: String,
+        attrs: Vec<syn::Attribute>,
+        public: bool,
+    ) {
+        if !self.push_version_from_attributes(name.clone(), attrs, public) {
+            self.push_path(name);
+            self.current_item_mut().public = public;
+            self.pop_path();
+        }
+    }
+
+    fn push_inherited_version_from_attributes(
+        &mut self,
+        name: String,
+        mut attrs: Vec<syn::Attribute>,
+        public: bool,
     ) {
-        let Ok(stable) = deluxe::extract_attributes::<_, Stable>(&mut attrs) else {
-            return;
+        let deprecation = Deprecation::from_attributes(&attrs);
+        let const_since = const_stable_since(&attrs);
+        let (stability, feature) = match extract_stability(&mut attrs) {
+            Some((stability, feature)) => (stability, Some(feature)),
+            None => (Stability::Inherited, None),
         };
 
         self.push_path(name);
-        self.push_version(stable.since, public);
+        self.push_version(stability, feature, public, deprecation, const_since);
         self.pop_path();
     }
 
@@ -362,198 +1090,954 @@ impl VersionConstructor {
         current
     }
 
-    fn push_version(&mut self, version: String, public: bool) {
+    fn push_version(
+        &mut self,
+        stability: Stability,
+        feature: Option<String>,
+        public: bool,
+        deprecation: Option<Deprecation>,
+        const_since: Option<String>,
+    ) {
         let current = self.current_item_mut();
-        current.version = version;
+        current.stability = stability;
+        current.feature = feature;
         current.public = public;
+        current.deprecation = deprecation;
+        current.const_since = const_since;
+    }
+
+    // Walks the tree itself, without following any aliases.
+    fn get_canonical(&self, path: &[String]) -> Option<&VersionedItem> {
+        let mut current = &self.root;
+        for segment in path {
+            current = current.children.get(segment)?;
+        }
+
+        Some(current)
+    }
+
+    /// Looks up the item at a path as it would be written in user code, like `Vec`
+    /// (from the prelude) or `std::collections::HashMap`. Private std internals can't be
+    /// named and don't resolve.
+    pub fn get_item(&self, path: &[String]) -> Option<&VersionedItem> {
+        let export = self.exports.get(&export_key(path))?;
+        let mut current = &self.root;
+        for segment in export.item.split("::") {
+            current = current.children.get(segment)?;
+        }
+
+        Some(current)
+    }
+
+    /// Resolves a macro invocation path, where bare names refer to exported macros. Returns
+    /// the path the macro was found at as well.
+    pub fn get_macro(&self, path: &[String]) -> Option<(Vec<String>, &VersionedItem)> {
+        let (name, parent) = path.split_last()?;
+        let name = format!("{name}!");
+
+        if !parent.is_empty() {
+            let mut full_path = parent.to_vec();
+            full_path.push(name.clone());
+
+            if let Some(item) = self.get_item(&full_path) {
+                return Some((full_path, item));
+            }
+        }
+
+        // Exported macros are re-exported by std, so look at the defining crate first.
+        CRATES.iter().find_map(|crate_| {
+            let full_path = vec![crate_.to_string(), name.clone()];
+            let item = self.get_item(&full_path)?;
+            Some((full_path, item))
+        })
+    }
+
+    /// The stability of the item at `path`, looking up the parent if it is inherited.
+    pub fn effective_stability<'a>(
+        &'a self,
+        path: &[String],
+        item: &'a VersionedItem,
+    ) -> &'a Stability {
+        self.annotated_item(path, item)
+            .map_or(&Stability::Unknown, |item| &item.stability)
+    }
+
+    /// The feature of the stability attribute that applies to the item at `path`.
+    pub fn effective_feature<'a>(
+        &'a self,
+        path: &[String],
+        item: &'a VersionedItem,
+    ) -> Option<&'a str> {
+        self.annotated_item(path, item)?.feature.as_deref()
     }
 
-    fn resolve_path_from<'a>(
+    // The item itself, or the closest annotated parent of its canonical path if its stability
+    // is inherited. The parent of a re-exported variant like `Some` is the enum, not the prelude.
+    fn annotated_item<'a>(
         &'a self,
-        root: &'a VersionedItem,
-        root_path: &[String],
         path: &[String],
+        item: &'a VersionedItem,
     ) -> Option<&'a VersionedItem> {
-        trace!("root = {root_path:?} = {}", root.name);
-        trace!("resolving {path:?}...");
+        if item.stability != Stability::Inherited {
+            return Some(item);
+        }
+
+        let export = self.exports.get(&export_key(path))?;
+        let mut parent = export
+            .item
+            .split("::")
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        parent.pop();
+        self.canonical_annotated_item(&parent)
+    }
 
-        let mut current = root;
-        let mut last: Option<&VersionedItem> = None;
+    fn canonical_annotated_item(&self, path: &[String]) -> Option<&VersionedItem> {
+        let item = self.get_canonical(path).filter(|_| !path.is_empty())?;
 
-        if !root_path.is_empty() && !path.is_empty() {
-            if path[0] == "crate" {
-                let new_root = self.root.children.get(&root_path[0])?;
-                trace!("{:?} crate shorthands to {}", &path[..1], root_path[0]);
-                return self.resolve_path_from(new_root, &root_path[..1], &path[1..]);
-            }
+        match item.stability {
+            Stability::Inherited => self.canonical_annotated_item(&path[..path.len() - 1]),
+            _ => Some(item),
+        }
+    }
 
-            // FIXME: HACK
-            if path[0] == "alloc_crate" {
-                let new_root = self.root.children.get("alloc")?;
-                trace!("alloc_crate shorthands to alloc");
-                return self.resolve_path_from(new_root, &["alloc".to_string()], &path[1..]);
+    /// The single public path of the item at `path`, so that re-exports are counted as one
+    /// item. This is where the item is defined if that can be named, like `alloc::vec::Vec`
+    /// for `std::vec::Vec`, and the closest re-export otherwise, like
+    /// `std::collections::hash_map::HashMap` for `std::collections::HashMap`.
+    pub fn canonical_path(&self, path: &[String]) -> Option<&str> {
+        self.exports
+            .get(&export_key(path))
+            .map(|export| export.canonical.as_str())
+    }
+
+    /// Every path in the export index with its stability, sorted by path.
+    pub fn exported_items(&self) -> Vec<ExportedItem> {
+        let mut items = self
+            .exports
+            .iter()
+            .filter_map(|(path, export)| {
+                let segments = path.split("::").map(str::to_string).collect::<Vec<_>>();
+                let item = self.get_item(&segments)?;
+                let annotated = self.annotated_item(&segments, item);
+
+                Some(ExportedItem {
+                    path: path.clone(),
+                    canonical: export.canonical.clone(),
+                    since: match annotated.map(|item| &item.stability) {
+                        Some(Stability::Stable(since)) => Some(since.clone()),
+                        _ => None,
+                    },
+                    feature: annotated.and_then(|item| item.feature.clone()),
+                    unstable: matches!(
+                        annotated.map(|item| &item.stability),
+                        Some(Stability::Unstable(_))
+                    ),
+                    public: item.public,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        items.sort_by(|a, b| a.path.cmp(&b.path));
+        items
+    }
+
+    /// The stability of the closest annotated ancestor of `path`.
+    pub fn ancestor_stability(&self, path: &[String]) -> &Stability {
+        for len in (1..path.len()).rev() {
+            let parent = &path[..len];
+            let Some(item) = self.get_item(parent) else {
+                continue;
+            };
+
+            let stability = self.effective_stability(parent, item);
+            if *stability != Stability::Unknown {
+                return stability;
             }
         }
 
-        let mut root_path = root_path.to_vec();
-        if !root_path.is_empty() && root_path[0] == "alloc_crate" {
-            root_path[0] = "alloc".to_string();
+        &Stability::Unknown
+    }
+
+    /// The deprecation of a method name, if every std method with that name is deprecated.
+    pub fn get_deprecated_method(&self, name: &str) -> Option<&Deprecation> {
+        self.methods.get(name)?.as_ref()
+    }
+}
+
+// Resolves all aliases once, so that lookups don't have to follow alias chains.
+struct ExportIndexBuilder<'a> {
+    database: &'a VersionConstructor,
+    aliases: HashMap<&'a [String], Vec<&'a Alias>>,
+    // Canonical paths of names in modules, by `module::name` and whether only names that
+    // are visible outside of std count.
+    members: HashMap<(Vec<String>, bool), Option<Vec<String>>>,
+    names: HashMap<(Vec<String>, bool), Vec<String>>,
+    in_progress: HashSet<(Vec<String>, bool)>,
+    // Visible paths, with the node that has their stability and the item they refer to.
+    visible: Vec<(Vec<String>, Vec<String>, Vec<String>)>,
+    cycles: BTreeSet<String>,
+}
+
+impl<'a> ExportIndexBuilder<'a> {
+    fn new(database: &'a VersionConstructor) -> ExportIndexBuilder<'a> {
+        let mut aliases: HashMap<&[String], Vec<&Alias>> = HashMap::new();
+        for alias in database.aliases.iter() {
+            aliases.entry(&alias.root).or_default().push(alias);
         }
 
-        for (i, segment) in path.iter().enumerate() {
-            if segment == "super" {
-                trace!("FIXME: super not supported");
-                return None;
-            }
+        ExportIndexBuilder {
+            database,
+            aliases,
+            members: HashMap::new(),
+            names: HashMap::new(),
+            in_progress: HashSet::new(),
+            visible: Vec::new(),
+            cycles: BTreeSet::new(),
+        }
+    }
+
+    fn build(mut self) -> HashMap<String, Export> {
+        self.visit(&mut Vec::new(), Vec::new(), &mut Vec::new());
+
+        for cycle in self.cycles.iter() {
+            warn!("alias cycle at {cycle}");
+        }
+
+        let mut paths_by_target: HashMap<&[String], Vec<&[String]>> = HashMap::new();
+        for (path, _, target) in self.visible.iter() {
+            paths_by_target.entry(target).or_default().push(path);
+        }
+
+        let canonical = paths_by_target
+            .into_iter()
+            .map(|(target, paths)| (target, preferred_path(target, &paths).join("::")))
+            .collect::<HashMap<_, _>>();
+
+        self.visible
+            .iter()
+            .map(|(path, item, target)| {
+                let export = Export {
+                    item: item.join("::"),
+                    canonical: canonical[target.as_slice()].clone(),
+                };
+
+                (path.join("::"), export)
+            })
+            .collect()
+    }
 
-            if segment == "self" {
+    // Aliases of `name!` for the named aliases that lead to a macro, which are not known yet, and
+    // whether nothing but the macro has that name.
+    fn macro_reexports(&mut self) -> Vec<(Alias, bool)> {
+        let mut reexports = Vec::new();
+        for alias in self.database.aliases.iter() {
+            let LocalAlias::Named(local) = &alias.local else {
+                continue;
+            };
+
+            let Some((name, module)) = alias.relative_path.split_last() else {
+                continue;
+            };
+
+            if name.ends_with('!') {
                 continue;
             }
 
-            let next = current.children.get(segment);
-            if let Some(next) = next {
-                last = Some(current);
-                current = next;
+            let macro_alias = Alias {
+                root: alias.root.clone(),
+                relative_path: [module, &[format!("{name}!")]].concat(),
+                local: LocalAlias::Named(format!("{local}!")),
+                public: alias.public,
+            };
+
+            if self.database.aliases.contains(&macro_alias)
+                || reexports.iter().any(|(known, _)| *known == macro_alias)
+                || self
+                    .resolve(&alias.root, &macro_alias.relative_path)
+                    .is_none()
+            {
                 continue;
             }
 
-            trace!("not found: {i} = {segment} (full = {path:?}, we are in {root_path:?})");
-            let mut path_until_here = root_path.to_vec();
-            path_until_here.extend_from_slice(&path[..i]);
-            let l = path_until_here.pop()?;
+            let macro_only = self.resolve(&alias.root, &alias.relative_path).is_none();
+            reexports.push((macro_alias, macro_only));
+        }
 
-            trace!("lets search for aliases starting at {:?}", path_until_here);
+        reexports
+    }
 
-            let aliases = self
-                .aliases
-                .iter()
-                .filter(|alias| alias.root == path_until_here);
-
-            trace!("candidates:");
-            for alias in aliases {
-                let root = &alias.root;
-                let rel = &alias.relative_path;
-                match &alias.local {
-                    LocalAlias::Named(name) => trace!("  {root:?} :: {name} -> {rel:?}"),
-                    LocalAlias::GlobChildren => trace!("  {root:?} :: * -> {rel:?}"),
-                }
+    // Records everything reachable from a path. Re-exports of an enclosing item (like a module
+    // that re-exports its crate) would make this go on forever, so we stop there.
+    fn visit(
+        &mut self,
+        visible: &mut Vec<String>,
+        canonical: Vec<String>,
+        ancestors: &mut Vec<Vec<String>>,
+    ) {
+        if ancestors.contains(&canonical) {
+            self.cycles.insert(visible.join("::"));
+            return;
+        }
 
-                match &alias.local {
-                    LocalAlias::Named(name) => {
-                        if name != &l {
-                            continue;
-                        }
+        for name in self.names(&canonical, true) {
+            let Some(target) = self.member(&canonical, &name, true) else {
+                continue;
+            };
+
+            let mut child = canonical.clone();
+            child.push(name.clone());
+
+            // A re-export has its own node with its own stability, but its children are the
+            // target's.
+            let item = if self.database.get_canonical(&child).is_some() {
+                child
+            } else {
+                target.clone()
+            };
+
+            visible.push(name);
+            self.visible.push((visible.clone(), item, target.clone()));
+
+            ancestors.push(canonical.clone());
+            self.visit(visible, target, ancestors);
+            ancestors.pop();
+            visible.pop();
+        }
+    }
 
-                        let last = last
-                            .or_else(|| self.resolve_path_from(&self.root, &[], &path_until_here))
-                            .unwrap();
+    // All names that can be looked up in a module, which includes glob imports. Outside of
+    // std (`exported`), only public items and `pub use`s can be named.
+    fn names(&mut self, module: &[String], exported: bool) -> Vec<String> {
+        let key = (module.to_vec(), exported);
+        if let Some(names) = self.names.get(&key) {
+            return names.clone();
+        }
 
-                        trace!(
-                            "found named match! current = {}, last = {}",
-                            current.name,
-                            last.name
-                        );
+        let progress_key = ([module, &["*".to_string()]].concat(), exported);
+        if !self.in_progress.insert(progress_key.clone()) {
+            self.cycles.insert(progress_key.0.join("::"));
+            return Vec::new();
+        }
 
-                        if let Some(new_root) =
-                            self.resolve_path_from(last, &path_until_here, &alias.relative_path)
-                        {
-                            path_until_here.extend(alias.relative_path.iter().cloned());
-                            trace!("re-rooting to {path_until_here:?} for {name}");
-                            return self.resolve_path_from(new_root, &path_until_here, &path[i..]);
-                        }
-
-                        let new_root =
-                            self.resolve_path_from(&self.root, &[], &alias.relative_path)?;
-                        trace!(
-                            "absolute re-rooting to {:?} for {name}",
-                            &alias.relative_path
-                        );
-                        return self.resolve_path_from(new_root, &alias.relative_path, &path[i..]);
-                    }
-                    LocalAlias::GlobChildren => {
-                        let last = last
-                            .or_else(|| self.resolve_path_from(&self.root, &[], &path_until_here))
-                            .unwrap();
-
-                        if let Some(new_root) =
-                            self.resolve_path_from(last, &path_until_here, &alias.relative_path)
-                        {
-                            if new_root.children.contains_key(&l) {
-                                path_until_here.extend(alias.relative_path.iter().cloned());
-                                trace!("re-rooting to {path_until_here:?} for *");
-                                return self.resolve_path_from(
-                                    new_root,
-                                    &path_until_here,
-                                    &path[i..],
-                                );
-                            }
-                        }
-
-                        let Some(new_root) =
-                            self.resolve_path_from(&self.root, &[], &alias.relative_path)
-                        else {
-                            continue;
-                        };
-
-                        if new_root.children.contains_key(&l) {
-                            trace!("absolute re-rooting to {:?} for *", &alias.relative_path);
-                            return self.resolve_path_from(
-                                new_root,
-                                &alias.relative_path,
-                                &path[i..],
-                            );
-                        }
+        let mut names = BTreeSet::new();
+        if let Some(item) = self.database.get_canonical(module) {
+            names.extend(
+                item.children
+                    .iter()
+                    .filter(|(_, child)| !exported || child.public)
+                    .map(|(name, _)| name.clone()),
+            );
+        }
+
+        for alias in self.aliases.get(module).cloned().unwrap_or_default() {
+            if exported && !alias.public {
+                continue;
+            }
+
+            match &alias.local {
+                LocalAlias::Named(name) => {
+                    names.insert(name.clone());
+                }
+                LocalAlias::GlobChildren => {
+                    if let Some(target) = self.resolve(module, &alias.relative_path) {
+                        names.extend(self.names(&target, exported));
                     }
                 }
             }
+        }
+
+        self.in_progress.remove(&progress_key);
+
+        let names = names.into_iter().collect::<Vec<_>>();
+        self.names.insert(key, names.clone());
+        names
+    }
 
+    // The canonical path of a name in a module, following aliases and glob imports.
+    fn member(&mut self, module: &[String], name: &str, exported: bool) -> Option<Vec<String>> {
+        let key = ([module, &[name.to_string()]].concat(), exported);
+
+        if let Some(member) = self.members.get(&key) {
+            return member.clone();
+        }
+
+        if !self.in_progress.insert(key.clone()) {
+            self.cycles.insert(key.0.join("::"));
             return None;
         }
 
+        let member = self.find_member(module, name, exported);
+
+        self.in_progress.remove(&key);
+        self.members.insert(key, member.clone());
+        member
+    }
+
+    fn find_member(
+        &mut self,
+        module: &[String],
+        name: &str,
+        exported: bool,
+    ) -> Option<Vec<String>> {
+        let aliases = self
+            .aliases
+            .get(module)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|alias| !exported || alias.public)
+            .collect::<Vec<_>>();
+
+        for alias in aliases.iter() {
+            if matches!(&alias.local, LocalAlias::Named(local) if local == name) {
+                if let Some(target) = self.resolve(module, &alias.relative_path) {
+                    return Some(target);
+                }
+            }
+        }
+
+        if self
+            .child(module, name)
+            .is_some_and(|child| !exported || child.public)
+        {
+            let mut path = module.to_vec();
+            path.push(name.to_string());
+            return Some(path);
+        }
+
+        for alias in aliases.iter() {
+            if let LocalAlias::GlobChildren = alias.local {
+                let Some(target) = self.resolve(module, &alias.relative_path) else {
+                    continue;
+                };
+
+                if let Some(member) = self.member(&target, name, exported) {
+                    return Some(member);
+                }
+            }
+        }
+
+        None
+    }
+
+    fn child(&self, module: &[String], name: &str) -> Option<&'a VersionedItem> {
+        self.database.get_canonical(module)?.children.get(name)
+    }
+
+    fn extern_crate(&self, module: &[String], name: &str) -> Option<&'a str> {
+        let krate = self.database.extern_crates.get(module.first()?)?;
+        krate.get(name).map(String::as_str)
+    }
+
+    fn has_named_alias(&self, module: &[String], name: &str) -> bool {
+        self.aliases.get(module).is_some_and(|aliases| {
+            aliases
+                .iter()
+                .any(|alias| matches!(&alias.local, LocalAlias::Named(local) if local == name))
+        })
+    }
+
+    // Resolves a `use` path in a module to a canonical path. Within std, private items can
+    // be used as well.
+    fn resolve(&mut self, module: &[String], path: &[String]) -> Option<Vec<String>> {
+        let (first, rest) = path.split_first()?;
+
+        let mut current = match first.as_str() {
+            "crate" => module.get(..1)?.to_vec(),
+            "self" => module.to_vec(),
+            "super" => module.get(..module.len().checked_sub(1)?)?.to_vec(),
+            // Paths are relative to the module in 2015 code, and start with a crate otherwise.
+            // Crates come before glob imports, which could otherwise lead back to this module.
+            _ if self.child(module, first).is_some() || self.has_named_alias(module, first) => {
+                self.member(module, first, false)?
+            }
+            _ => match self.extern_crate(module, first) {
+                Some(krate) => vec![krate.to_string()],
+                None if self.child(&[], first).is_some() => vec![first.clone()],
+                None => self.member(module, first, false)?,
+            },
+        };
+
+        for segment in rest {
+            match segment.as_str() {
+                "self" => {}
+                "super" => {
+                    current.pop()?;
+                }
+                _ => current = self.member(&current, segment, false)?,
+            }
+        }
+
         Some(current)
     }
+}
+
+// The path users are pointed to for an item: where it is defined if that can be named, and
+// otherwise the re-export closest to it, preferring modules over the shortcuts above them.
+fn preferred_path<'p>(target: &[String], paths: &[&'p [String]]) -> &'p [String] {
+    let key = |path: &[String]| {
+        let common = path.iter().zip(target).take_while(|(a, b)| a == b).count();
+        (path == target, common, path.len())
+    };
+
+    paths
+        .iter()
+        .copied()
+        .max_by(|a, b| key(a).cmp(&key(b)).then_with(|| b.cmp(a)))
+        .expect("every target has a visible path")
+}
+
+fn export_key(path: &[String]) -> String {
+    path.iter()
+        .filter(|segment| *segment != "self")
+        .join_with("::")
+        .to_string()
+}
 
-    pub fn get_version(&self, path: &[String]) -> Option<&str> {
-        self.resolve_path_from(&self.root, &[], path)
-            .map(|item| item.version.as_str())
+// Also returns the feature name, which both stable and unstable items have.
+fn extract_stability(attrs: &mut Vec<syn::Attribute>) -> Option<(Stability, String)> {
+    if let Ok(stable) = deluxe::extract_attributes::<_, Stable>(attrs) {
+        Some((Stability::Stable(stable.since), stable.feature))
+    } else if let Ok(unstable) = deluxe::extract_attributes::<_, Unstable>(attrs) {
+        Some((
+            Stability::Unstable(unstable.feature.clone()),
+            unstable.feature,
+        ))
+    } else {
+        None
     }
 }
 
+// `#[rustc_const_stable(feature = "...", since = "...")]` marks when a function became usable in
+// const contexts.
+fn const_stable_since(attrs: &[syn::Attribute]) -> Option<String> {
+    let attr = attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("rustc_const_stable"))?;
+
+    let mut since = None;
+    let _ = attr.parse_nested_meta(|meta| {
+        let value = meta.value()?.parse::<syn::LitStr>()?.value();
+        if meta.path.is_ident("since") {
+            since = Some(value);
+        }
+
+        Ok(())
+    });
+
+    since
+}
+
+// Ids are strings in older format versions and numbers in newer ones.
+fn rustdoc_id(id: &Value) -> Option<String> {
+    match id {
+        Value::String(id) => Some(id.clone()),
+        Value::Number(id) => Some(id.to_string()),
+        _ => None,
+    }
+}
+
+// rustdoc keeps attributes as source strings, except for deprecations, which have their own field.
+fn rustdoc_attrs(item: &Value) -> Vec<syn::Attribute> {
+    let mut attrs = item["attrs"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        // Newer format versions wrap attributes without a structured form in `other`.
+        .filter_map(|attr| attr.as_str().or_else(|| attr["other"].as_str()))
+        .filter_map(|attr| syn::parse::Parser::parse_str(syn::Attribute::parse_outer, attr).ok())
+        .flatten()
+        .collect::<Vec<_>>();
+
+    if let Some(deprecation) = item["deprecation"].as_object() {
+        let args = ["since", "note"]
+            .into_iter()
+            .filter_map(|key| Some(format!("{key} = {:?}", deprecation.get(key)?.as_str()?)))
+            .collect::<Vec<_>>();
+
+        if let Ok(attr) = syn::parse::Parser::parse_str(
+            syn::Attribute::parse_outer,
+            &format!("#[deprecated({})]", args.join(", ")),
+        ) {
+            attrs.extend(attr);
+        }
+    }
+
+    attrs
+}
+
 fn is_public(vis: syn::Visibility) -> bool {
     matches!(vis, syn::Visibility::Public(_))
 }
 
-const CRATES: &[&str] = &["alloc", "core", "std"];
-const CACHE_FILE: &str = "cache.json";
+/// The crates that make up the standard library.
+pub const CRATES: &[&str] = &["alloc", "core", "std"];
+
+/// Bumped whenever the database layout changes, so that old caches are rebuilt.
+const SCHEMA_VERSION: u32 = 5;
+
+#[derive(Serialize, Deserialize)]
+struct Cache<D> {
+    metadata: CacheMetadata,
+    database: D,
+}
+
+/// What a database was built from, which decides whether it can be rebuilt automatically.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheSource {
+    /// The `expanded-*.rs` files in the working directory.
+    Expanded,
+    /// The `rust-src` component of the active toolchain, for every target.
+    DefaultRustSrc,
+    /// Explicitly given inputs, like another toolchain, targets, rustdoc JSON or merged snapshots.
+    Custom,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CacheMetadata {
+    schema_version: u32,
+    #[serde(default)]
+    source: Option<CacheSource>,
+    /// FNV-1a hashes of the `expanded-*.rs` files the database was built from.
+    source_hashes: BTreeMap<String, String>,
+    /// Version of the active toolchain, if the database was built from its `rust-src`.
+    rustc_version: Option<String>,
+}
+
+impl CacheMetadata {
+    /// Metadata for databases built from explicitly given inputs, which are never rebuilt.
+    pub fn pinned() -> CacheMetadata {
+        CacheMetadata {
+            schema_version: SCHEMA_VERSION,
+            source: Some(CacheSource::Custom),
+            source_hashes: BTreeMap::new(),
+            rustc_version: None,
+        }
+    }
+
+    pub fn active_toolchain() -> anyhow::Result<CacheMetadata> {
+        Ok(CacheMetadata {
+            source: Some(CacheSource::DefaultRustSrc),
+            rustc_version: Some(toolchain_version(None)?),
+            ..CacheMetadata::pinned()
+        })
+    }
+
+    // Older caches don't record their source, but it follows from what they track.
+    fn source(&self) -> CacheSource {
+        self.source.unwrap_or(if self.rustc_version.is_some() {
+            CacheSource::DefaultRustSrc
+        } else if !self.source_hashes.is_empty() {
+            CacheSource::Expanded
+        } else {
+            CacheSource::Custom
+        })
+    }
+
+    // Rebuilding a custom database from the defaults would silently throw away what it was built
+    // from, so the user has to do it.
+    fn ensure_rebuildable(&self, path: &Path) -> anyhow::Result<()> {
+        ensure!(
+            self.source() != CacheSource::Custom,
+            "{} is outdated, but was not built from the default inputs, so it cannot be rebuilt \
+             automatically; regenerate it with `build-std-db` or `merge-std-db`",
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    // Inputs that are missing now don't make the cache stale, as it may have been copied.
+    fn is_stale(&self) -> bool {
+        if self.schema_version != SCHEMA_VERSION {
+            debug!("cache has schema version {}", self.schema_version);
+            return true;
+        }
+
+        for (file, hash) in self.source_hashes.iter() {
+            if let Ok(source) = std::fs::read(file) {
+                if fnv1a(&source) != *hash {
+                    debug!("{file} changed since the cache was built");
+                    return true;
+                }
+            }
+        }
+
+        if let Some(version) = &self.rustc_version {
+            if toolchain_version(None).is_ok_and(|current| current != *version) {
+                debug!("cache was built for rustc {version}");
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
 
-pub fn load_version_constructor() -> anyhow::Result<VersionConstructor> {
-    if let Ok(file) = File::open(CACHE_FILE) {
-        debug!("using existing cache file..");
-        serde_json::from_reader(file).context("failed to parse context")
+    format!("{hash:016x}")
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+fn read_cache<D: DeserializeOwned>(path: &Path) -> anyhow::Result<Cache<D>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+
+    if is_json(path) {
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
     } else {
-        debug!("creating new cache file..");
-        let mut version_constructor = VersionConstructor::new();
+        ciborium::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
 
-        for crate_ in CRATES {
-            version_constructor.process_file(
-                crate_.to_string(),
-                syn::parse_file(
-                    &std::fs::read_to_string(format!("expanded-{crate_}.rs"))
-                        .with_context(|| format!("failed to read expanded-{crate_}.rs"))?,
-                )
+/// Loads the database from the cache at `path`, (re)building it from the `expanded-*.rs`
+/// files or the active toolchain's `rust-src` if the cache is missing or stale. Caches built
+/// from other inputs are never rebuilt, so this fails if they are stale.
+pub fn load_version_constructor(path: &Path) -> anyhow::Result<VersionConstructor> {
+    let stale = match read_cache::<VersionConstructor>(path) {
+        Ok(cache) if !cache.metadata.is_stale() => {
+            debug!("using existing cache file..");
+            return Ok(cache.database);
+        }
+        Ok(cache) => {
+            cache.metadata.ensure_rebuildable(path)?;
+            Some(cache.database)
+        }
+        Err(err) => {
+            // The database layout may have changed, but the metadata tells us how it was built.
+            if let Ok(cache) = read_cache::<IgnoredAny>(path) {
+                cache.metadata.ensure_rebuildable(path)?;
+            }
+
+            debug!("could not use cache file: {err:#}");
+            None
+        }
+    };
+
+    debug!("creating new cache file..");
+    let rebuilt = if CRATES
+        .iter()
+        .all(|crate_| Path::new(&format!("expanded-{crate_}.rs")).is_file())
+    {
+        build_version_constructor_from_expanded()
+    } else {
+        rust_src_library(None)
+            .and_then(|library| build_version_constructor(&library, None, &[]))
+            .and_then(|version_constructor| {
+                Ok((version_constructor, CacheMetadata::active_toolchain()?))
+            })
+    };
+
+    match (rebuilt, stale) {
+        (Ok((version_constructor, metadata)), _) => {
+            version_constructor.save(path, metadata)?;
+            Ok(version_constructor)
+        }
+        (Err(err), Some(stale)) => {
+            warn!("could not rebuild stale cache, using it anyway: {err:#}");
+            Ok(stale)
+        }
+        (Err(err), None) => Err(err),
+    }
+}
+
+fn build_version_constructor_from_expanded() -> anyhow::Result<(VersionConstructor, CacheMetadata)>
+{
+    let mut version_constructor = VersionConstructor::new();
+    let mut metadata = CacheMetadata {
+        source: Some(CacheSource::Expanded),
+        ..CacheMetadata::pinned()
+    };
+
+    for crate_ in CRATES {
+        let file = format!("expanded-{crate_}.rs");
+        let source =
+            std::fs::read_to_string(&file).with_context(|| format!("failed to read {file}"))?;
+
+        version_constructor.process_file(
+            crate_.to_string(),
+            syn::parse_file(&source)
                 .with_context(|| format!("failed to parse {crate_} expanded source code"))?,
-            );
+        );
+
+        metadata
+            .source_hashes
+            .insert(file, fnv1a(source.as_bytes()));
+    }
+
+    version_constructor.finish();
+
+    Ok((version_constructor, metadata))
+}
+
+/// Builds the database from the unexpanded sources of a toolchain's `rust-src` component,
+/// which are in the `library` directory. Without targets, every platform-specific module
+/// is included; with targets, the `#[cfg]`s are evaluated with the toolchain's `rustc` and the
+/// results merged.
+pub fn build_version_constructor(
+    library: &Path,
+    toolchain: Option<&str>,
+    targets: &[String],
+) -> anyhow::Result<VersionConstructor> {
+    let mut version_constructor = if targets.is_empty() {
+        build_library(library, None)?
+    } else {
+        let mut version_constructor = VersionConstructor::new();
+        for target in targets {
+            debug!("evaluating cfgs for {target}..");
+            let cfg = TargetCfg::from_rustc(toolchain, target)?;
+            version_constructor.merge_target(target, build_library(library, Some(&cfg))?);
         }
 
-        version_constructor.aliases.push(Alias {
-            root: vec![],
-            relative_path: vec!["std".to_string(), "prelude".to_string(), "v1".to_string()],
-            local: LocalAlias::GlobChildren,
-        });
+        version_constructor
+    };
+
+    version_constructor.finish();
+
+    Ok(version_constructor)
+}
+
+fn build_library(library: &Path, cfg: Option<&TargetCfg>) -> anyhow::Result<VersionConstructor> {
+    let mut version_constructor = VersionConstructor::new();
+
+    for crate_ in CRATES {
+        debug!("processing {crate_} sources..");
+        let root = library.join(crate_).join("src").join("lib.rs");
+        let sources =
+            load_module_tree(&root).with_context(|| format!("failed to load {crate_} sources"))?;
+        let mut file = merge_module_tree(&sources)
+            .with_context(|| format!("failed to parse {crate_} sources"))?;
+
+        if let Some(cfg) = cfg {
+            remove_disabled_items(&mut file.items, cfg);
+        }
+
+        version_constructor.process_file(crate_.to_string(), file);
+    }
+
+    Ok(version_constructor)
+}
+
+/// Builds the database from rustdoc's JSON output for each of the std crates, which
+/// should be in `dir` as `core.json`, `alloc.json` and `std.json`.
+pub fn build_version_constructor_from_rustdoc(dir: &Path) -> anyhow::Result<VersionConstructor> {
+    let mut version_constructor = VersionConstructor::new();
+
+    for crate_ in CRATES {
+        debug!("processing {crate_} rustdoc JSON..");
+        let path = dir.join(format!("{crate_}.json"));
+        let file =
+            File::open(&path).with_context(|| format!("failed to open {}", path.display()))?;
+        let krate: Value = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        trace!(
+            "{crate_} rustdoc format version {}",
+            krate["format_version"]
+        );
+        version_constructor
+            .process_rustdoc_json(crate_.to_string(), &krate)
+            .with_context(|| format!("failed to process {}", path.display()))?;
+    }
+
+    version_constructor.finish();
+
+    Ok(version_constructor)
+}
 
-        let file = File::create(CACHE_FILE).context("failed to create cache.json")?;
-        serde_json::to_writer(file, &version_constructor).context("failed to write context")?;
+/// Merges databases built for several Rust releases (e.g. with `build_version_constructor`)
+/// into one, so that items that were removed or moved later can still be resolved.
+pub fn merge_version_constructors(
+    snapshots: &[(String, PathBuf)],
+) -> anyhow::Result<VersionConstructor> {
+    let mut snapshots = snapshots.to_vec();
+    snapshots.sort_by_key(|(version, _)| parse_version(version));
+
+    let mut version_constructor = VersionConstructor::new();
+    for (version, path) in snapshots {
+        debug!("merging {version} snapshot from {}..", path.display());
+        let snapshot = read_cache::<VersionConstructor>(&path)?.database;
+        version_constructor.merge_snapshot(&version, snapshot);
+    }
+
+    version_constructor.finish();
+    version_constructor.resolve_successors();
+
+    Ok(version_constructor)
+}
+
+/// Splits a version into its numeric parts, so that versions can be compared.
+pub fn parse_version(version: &str) -> Vec<u32> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or_default())
+        .collect()
+}
+
+/// Whether two versions are the same release, so that `1.70` matches `1.70.0`.
+pub fn is_same_release(a: &str, b: &str) -> bool {
+    let (mut a, mut b) = (parse_version(a), parse_version(b));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a == b
+}
+
+// The version of a toolchain, e.g. `1.75.0` from `rustc 1.75.0 (82e1608df 2023-12-21)`.
+pub fn toolchain_version(toolchain: Option<&str>) -> anyhow::Result<String> {
+    let mut rustc = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        rustc.arg(format!("+{toolchain}"));
+    }
+    let rustc = rustc
+        .arg("--version")
+        .output()
+        .context("failed to execute rustc")?;
+
+    let out = String::from_utf8(rustc.stdout)?;
+    out.split_whitespace()
+        .nth(1)
+        .map(str::to_string)
+        .context("could not parse rustc version")
+}
 
-        Ok(version_constructor)
+/// The `library` directory of the `rust-src` component of a (rustup) toolchain.
+pub fn rust_src_library(toolchain: Option<&str>) -> anyhow::Result<PathBuf> {
+    let mut rustc = Command::new("rustc");
+    if let Some(toolchain) = toolchain {
+        rustc.arg(format!("+{toolchain}"));
     }
+    let rustc = rustc
+        .args(["--print", "sysroot"])
+        .output()
+        .context("failed to execute rustc")?;
+
+    ensure!(
+        rustc.status.success(),
+        "could not find sysroot: {}",
+        String::from_utf8_lossy(&rustc.stderr).trim()
+    );
+
+    let sysroot = PathBuf::from(String::from_utf8(rustc.stdout)?.trim());
+    let library = sysroot.join("lib/rustlib/src/rust/library");
+    ensure!(
+        library.is_dir(),
+        "{} does not exist, is the rust-src component installed?",
+        library.display()
+    );
+
+    Ok(library)
 }